@@ -222,6 +222,34 @@ function handleTabUpdated(tabId, changeInfo, tab) {
   }
 }
 
+/**
+ * Reports the focused tab to the daemon so it can track daily allowances
+ */
+const ACTIVITY_INTERVAL_SECONDS = 15;
+
+function reportActivity() {
+  Promise.all([
+    browser.idle.queryState(ACTIVITY_INTERVAL_SECONDS),
+    browser.windows.getLastFocused(),
+    browser.tabs.query({ active: true, lastFocusedWindow: true })
+  ]).then(([idleState, window, tabs]) => {
+    const tab = tabs[0];
+    if (idleState !== "active" || !window.focused || !tab || !tab.url) return;
+    if (!tab.url.startsWith("http://") && !tab.url.startsWith("https://")) return;
+    if (isUrlBlocked(tab.url)) return;
+
+    port.postMessage({
+      type: "activity",
+      url: tab.url,
+      seconds: ACTIVITY_INTERVAL_SECONDS
+    });
+  }).catch(error => {
+    console.error("Error reporting activity:", error);
+  });
+}
+
+setInterval(reportActivity, ACTIVITY_INTERVAL_SECONDS * 1000);
+
 browser.tabs.onActivated.addListener(handleTabActivated);
 browser.tabs.onUpdated.addListener(handleTabUpdated);
 
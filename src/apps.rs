@@ -0,0 +1,444 @@
+use chrono::{DateTime, Duration, Local};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::fs;
+use std::path::Path;
+
+/// How long a terminated process gets to exit before it is killed.
+const TERMINATE_GRACE: Duration = Duration::seconds(5);
+
+/// What happens to a blocked application's processes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ApplicationAction {
+    /// SIGTERM, then SIGKILL if the process is still running after a grace
+    /// period.
+    #[default]
+    Terminate,
+    /// SIGSTOP, with a SIGCONT once no active block wants it stopped.
+    Suspend,
+}
+
+impl ApplicationAction {
+    pub fn parse(value: &str) -> Result<ApplicationAction, String> {
+        match value {
+            "terminate" => Ok(ApplicationAction::Terminate),
+            "suspend" => Ok(ApplicationAction::Suspend),
+            _ => Err(format!("'{value}' is not one of \"terminate\" or \"suspend\"")),
+        }
+    }
+}
+
+/// Checks an `applications` entry. A path needs at least two components,
+/// since "/" or "/usr/" would match nearly every process the user runs.
+pub fn check_pattern(pattern: &str) -> Result<(), String> {
+    let Some(program) = pattern.split_whitespace().next() else {
+        return Err("the pattern is empty".to_string());
+    };
+    if program.contains('/') && program.split('/').filter(|part| !part.is_empty()).count() < 2 {
+        return Err(format!(
+            "'{pattern}' is too broad; a path needs at least two components, e.g. \"/opt/games/\""
+        ));
+    }
+    Ok(())
+}
+
+/// A running process, as found in `/proc`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Process {
+    pub pid: i32,
+    /// The file name of the executable, or `comm` when the link can't be read.
+    pub name: String,
+    pub comm: String,
+    pub cmdline: Vec<String>,
+}
+
+impl Process {
+    /// A pattern matches the executable's name or `comm`, ignoring case, or
+    /// the start of the command line up to a whole argument, with or without
+    /// the first argument's directory: "steam", "/opt/games/", "python3 -m
+    /// mygame". Matching only the start keeps a shell or editor that
+    /// mentions a blocked program from matching.
+    pub fn matches(&self, pattern: &str) -> bool {
+        if self.name.eq_ignore_ascii_case(pattern) || self.comm.eq_ignore_ascii_case(pattern) {
+            return true;
+        }
+        let Some((first, rest)) = self.cmdline.split_first() else {
+            return false;
+        };
+        let rest = rest.iter().map(|arg| format!(" {arg}")).collect::<String>();
+        [first.as_str(), file_name(first)].into_iter().any(|first| {
+            let line = format!("{first}{rest}");
+            line.strip_prefix(pattern).is_some_and(|after| {
+                after.is_empty() || after.starts_with(' ') || pattern.ends_with('/')
+            })
+        })
+    }
+}
+
+/// The processes in `proc_dir` that belong to `uid`, leaving out kernel
+/// threads and the daemon itself.
+pub fn scan_processes(proc_dir: &Path, uid: u32) -> Vec<Process> {
+    let Ok(entries) = fs::read_dir(proc_dir) else {
+        return Vec::new();
+    };
+    let own_pid = std::process::id() as i32;
+    entries
+        .flatten()
+        .filter_map(|entry| {
+            let pid: i32 = entry.file_name().to_str()?.parse().ok()?;
+            if pid <= 1 || pid == own_pid {
+                return None;
+            }
+            let dir = entry.path();
+            if process_uid(&dir)? != uid {
+                return None;
+            }
+            let cmdline: Vec<String> = fs::read(dir.join("cmdline"))
+                .ok()?
+                .split(|byte| *byte == 0)
+                .filter(|arg| !arg.is_empty())
+                .map(|arg| String::from_utf8_lossy(arg).into_owned())
+                .collect();
+            // Kernel threads have no command line
+            if cmdline.is_empty() {
+                return None;
+            }
+            let comm = fs::read_to_string(dir.join("comm")).ok()?.trim_end().to_string();
+            let name = fs::read_link(dir.join("exe"))
+                .ok()
+                .and_then(|exe| exe.file_name()?.to_str().map(String::from))
+                // Replaced executables read as "name (deleted)"
+                .map(|name| name.trim_end_matches(" (deleted)").to_string())
+                .unwrap_or_else(|| comm.clone());
+            Some(Process {
+                pid,
+                name,
+                comm,
+                cmdline,
+            })
+        })
+        .collect()
+}
+
+/// The real user id from a process's `status` file.
+fn process_uid(dir: &Path) -> Option<u32> {
+    let status = fs::read_to_string(dir.join("status")).ok()?;
+    let ids = status.lines().find_map(|line| line.strip_prefix("Uid:"))?;
+    ids.split_whitespace().next()?.parse().ok()
+}
+
+/// The user the daemon runs as, whose processes it may stop.
+pub fn current_uid() -> u32 {
+    // SAFETY: getuid has no preconditions and can't fail
+    unsafe { libc::getuid() }
+}
+
+fn file_name(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Signal {
+    Terminate,
+    Kill,
+    Stop,
+    Continue,
+}
+
+impl Signal {
+    fn number(self) -> libc::c_int {
+        match self {
+            Signal::Terminate => libc::SIGTERM,
+            Signal::Kill => libc::SIGKILL,
+            Signal::Stop => libc::SIGSTOP,
+            Signal::Continue => libc::SIGCONT,
+        }
+    }
+
+    /// Past tense, for the log and `shire block list`.
+    pub fn describe(self) -> &'static str {
+        match self {
+            Signal::Terminate => "terminated",
+            Signal::Kill => "killed",
+            Signal::Stop => "suspended",
+            Signal::Continue => "resumed",
+        }
+    }
+}
+
+/// A signal sent, or about to be sent, to a blocked application.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Action {
+    pub block: String,
+    pub pid: i32,
+    pub name: String,
+    pub signal: Signal,
+    pub at: DateTime<Local>,
+}
+
+impl Action {
+    pub fn send(&self) -> Result<(), String> {
+        // SAFETY: kill has no memory safety requirements
+        if unsafe { libc::kill(self.pid, self.signal.number()) } == 0 {
+            Ok(())
+        } else {
+            Err(std::io::Error::last_os_error().to_string())
+        }
+    }
+}
+
+/// The applications an active block keeps from running.
+pub struct Rule<'a> {
+    pub block: &'a str,
+    pub applications: &'a [String],
+    pub action: ApplicationAction,
+}
+
+/// A process stopped with SIGSTOP. The daemon saves these so it can resume
+/// them after it restarts; the name keeps a reused pid from being resumed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Suspended {
+    pub pid: i32,
+    pub name: String,
+    pub block: String,
+}
+
+impl Suspended {
+    fn resume(&self, now: DateTime<Local>) -> Action {
+        Action {
+            block: self.block.clone(),
+            pid: self.pid,
+            name: self.name.clone(),
+            signal: Signal::Continue,
+            at: now,
+        }
+    }
+}
+
+/// Resumes the processes in `saved` that are still running under the same
+/// name.
+pub fn resume_saved(
+    saved: &[Suspended],
+    processes: &[Process],
+    now: DateTime<Local>,
+) -> Vec<Action> {
+    saved
+        .iter()
+        .filter(|suspended| {
+            processes
+                .iter()
+                .any(|process| process.pid == suspended.pid && process.name == suspended.name)
+        })
+        .map(|suspended| suspended.resume(now))
+        .collect()
+}
+
+/// Remembers the processes it has signalled between scans, so terminated
+/// ones are killed after the grace period and suspended ones are resumed
+/// once no active block wants them stopped.
+#[derive(Debug, Clone, Default)]
+pub struct Enforcer {
+    /// When each process was sent SIGTERM, and whether it has been killed.
+    terminating: HashMap<i32, (DateTime<Local>, bool)>,
+    suspended: HashMap<i32, Suspended>,
+}
+
+impl Enforcer {
+    /// The processes currently suspended, by pid.
+    pub fn suspended(&self) -> Vec<Suspended> {
+        let mut suspended: Vec<Suspended> = self.suspended.values().cloned().collect();
+        suspended.sort_by_key(|suspended| suspended.pid);
+        suspended
+    }
+
+    /// The signals to send for the current processes and active blocks.
+    /// Termination wins over suspension when both apply.
+    pub fn plan(
+        &mut self,
+        processes: &[Process],
+        rules: &[Rule],
+        now: DateTime<Local>,
+    ) -> Vec<Action> {
+        let running: HashMap<i32, &Process> =
+            processes.iter().map(|process| (process.pid, process)).collect();
+        self.terminating.retain(|pid, _| running.contains_key(pid));
+        self.suspended.retain(|pid, suspended| {
+            running.get(pid).is_some_and(|process| process.name == suspended.name)
+        });
+
+        let mut actions = Vec::new();
+        for process in processes {
+            let wanted = |action| {
+                rules.iter().find(|rule| {
+                    rule.action == action
+                        && rule.applications.iter().any(|pattern| process.matches(pattern))
+                })
+            };
+            let action = |block: &str, signal| Action {
+                block: block.to_string(),
+                pid: process.pid,
+                name: process.name.clone(),
+                signal,
+                at: now,
+            };
+
+            if let Some(rule) = wanted(ApplicationAction::Terminate) {
+                match self.terminating.get_mut(&process.pid) {
+                    None => {
+                        self.terminating.insert(process.pid, (now, false));
+                        actions.push(action(rule.block, Signal::Terminate));
+                    }
+                    Some((since, killed)) if !*killed && now - *since >= TERMINATE_GRACE => {
+                        *killed = true;
+                        actions.push(action(rule.block, Signal::Kill));
+                    }
+                    Some(_) => {}
+                }
+            } else if let Some(rule) = wanted(ApplicationAction::Suspend) {
+                if let Entry::Vacant(entry) = self.suspended.entry(process.pid) {
+                    entry.insert(Suspended {
+                        pid: process.pid,
+                        name: process.name.clone(),
+                        block: rule.block.to_string(),
+                    });
+                    actions.push(action(rule.block, Signal::Stop));
+                }
+            } else if let Some(suspended) = self.suspended.remove(&process.pid) {
+                actions.push(suspended.resume(now));
+            }
+        }
+        actions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn process(pid: i32, cmdline: &str) -> Process {
+        let cmdline: Vec<String> = cmdline.split(' ').map(String::from).collect();
+        let name = file_name(&cmdline[0]).to_string();
+        Process {
+            pid,
+            comm: name.chars().take(15).collect(),
+            name,
+            cmdline,
+        }
+    }
+
+    #[test]
+    fn test_broad_patterns_are_rejected() {
+        for pattern in ["steam", "/opt/games/", "python3 -m mygame", "bin/game"] {
+            assert_eq!(check_pattern(pattern), Ok(()), "{pattern}");
+        }
+        assert!(check_pattern("").unwrap_err().contains("empty"));
+        assert!(check_pattern("  ").unwrap_err().contains("empty"));
+        for pattern in ["/", "/usr/", "//", "/bin --flag", "./"] {
+            assert!(check_pattern(pattern).unwrap_err().contains("too broad"), "{pattern}");
+        }
+    }
+
+    #[test]
+    fn test_processes_match_names_and_command_lines() {
+        let discord = process(10, "/opt/discord/Discord --type=renderer");
+        assert!(discord.matches("discord"));
+        assert!(!discord.matches("disc"));
+        assert!(discord.matches("/opt/discord/"));
+        assert!(discord.matches("/opt/discord/Discord"));
+
+        let game = process(11, "/usr/bin/python3 -m mygame");
+        assert!(game.matches("python3 -m mygame"));
+        assert!(!game.matches("python3 -m my"));
+        assert!(!game.matches("mygame"));
+        assert!(!process(12, "vim steam").matches("steam"));
+        assert!(!process(13, "bash -c /opt/discord/Discord").matches("/opt/discord/"));
+    }
+
+    #[test]
+    fn test_enforcer_kills_after_grace_and_resumes_suspended() {
+        let now = Local.with_ymd_and_hms(2025, 9, 15, 9, 0, 0).unwrap();
+        let games = vec!["steam".to_string()];
+        let chat = vec!["discord".to_string()];
+        let terminate = Rule {
+            block: "games",
+            applications: &games,
+            action: ApplicationAction::Terminate,
+        };
+        let suspend = Rule {
+            block: "chat",
+            applications: &chat,
+            action: ApplicationAction::Suspend,
+        };
+        let processes = [process(10, "/usr/bin/steam"), process(11, "/opt/discord/Discord")];
+        let mut enforcer = Enforcer::default();
+        let signals = |actions: Vec<Action>| -> Vec<(i32, Signal)> {
+            actions.iter().map(|action| (action.pid, action.signal)).collect()
+        };
+
+        let rules = [terminate, suspend];
+        let planned = enforcer.plan(&processes, &rules, now);
+        assert_eq!(signals(planned), [(10, Signal::Terminate), (11, Signal::Stop)]);
+        let planned = enforcer.plan(&processes, &rules, now + Duration::seconds(2));
+        assert!(planned.is_empty());
+        let planned = enforcer.plan(&processes, &rules, now + Duration::seconds(5));
+        assert_eq!(signals(planned), [(10, Signal::Kill)]);
+
+        // The chat block ended, and steam is gone
+        let planned = enforcer.plan(&processes[1..], &rules[..1], now + Duration::seconds(6));
+        assert_eq!(signals(planned), [(11, Signal::Continue)]);
+        assert!(enforcer.plan(&processes[1..], &rules[..1], now + Duration::seconds(7)).is_empty());
+    }
+
+    #[test]
+    fn test_saved_suspended_processes_are_resumed() {
+        let now = Local.with_ymd_and_hms(2025, 9, 15, 9, 0, 0).unwrap();
+        let chat = vec!["discord".to_string()];
+        let rules = [Rule {
+            block: "chat",
+            applications: &chat,
+            action: ApplicationAction::Suspend,
+        }];
+        let processes = [process(11, "/opt/discord/Discord")];
+        let mut enforcer = Enforcer::default();
+        enforcer.plan(&processes, &rules, now);
+        let saved = enforcer.suspended();
+        assert_eq!(
+            saved,
+            [Suspended { pid: 11, name: "Discord".to_string(), block: "chat".to_string() }]
+        );
+
+        // Only the same process under the same pid is resumed
+        let resumed = resume_saved(&saved, &processes, now);
+        assert_eq!(resumed.len(), 1);
+        assert_eq!((resumed[0].pid, resumed[0].signal), (11, Signal::Continue));
+        assert_eq!(resumed[0].block, "chat");
+        assert!(resume_saved(&saved, &[process(11, "/usr/bin/vim")], now).is_empty());
+        assert!(resume_saved(&saved, &[], now).is_empty());
+    }
+
+    #[test]
+    fn test_scan_reads_proc_entries() {
+        let dir = std::env::temp_dir().join(format!("shire-proc-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        for (pid, uid, comm, cmdline) in [
+            ("4242", 1000, "steam\n", "/home/me/.steam/steam\0-silent\0"),
+            ("4243", 0, "kworker/0:1\n", ""),
+            ("4244", 1001, "steam\n", "/usr/bin/steam\0"),
+            ("self", 1000, "shire\n", "shire\0"),
+        ] {
+            fs::create_dir_all(dir.join(pid)).unwrap();
+            let status = format!("Name:\t{comm}Uid:\t{uid}\t{uid}\t{uid}\t{uid}\n");
+            fs::write(dir.join(pid).join("status"), status).unwrap();
+            fs::write(dir.join(pid).join("comm"), comm).unwrap();
+            fs::write(dir.join(pid).join("cmdline"), cmdline).unwrap();
+        }
+
+        // Another user's steam is left alone
+        let processes = scan_processes(&dir, 1000);
+        assert_eq!(processes, [process(4242, "/home/me/.steam/steam -silent")]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
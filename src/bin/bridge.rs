@@ -1,4 +1,5 @@
-use std::io::{self, Write};
+use serde_json::Value;
+use std::io::{self, Read, Write};
 use std::os::unix::net::UnixStream;
 use std::thread;
 use std::time::Duration;
@@ -13,10 +14,49 @@ fn write_browser_message(message: &str) -> io::Result<()> {
     Ok(())
 }
 
+fn read_browser_message() -> io::Result<String> {
+    let mut len_buf = [0u8; 4];
+    io::stdin().read_exact(&mut len_buf)?;
+    let len = u32::from_le_bytes(len_buf) as usize;
+
+    let mut buf = vec![0u8; len];
+    io::stdin().read_exact(&mut buf)?;
+    Ok(String::from_utf8_lossy(&buf).into_owned())
+}
+
+// Activity reports go through the daemon's CLI socket so the bridge socket
+// stays a one-way channel for state updates.
+fn forward_activity(report: &Value) -> io::Result<()> {
+    let request = serde_json::json!({
+        "action": "report_activity",
+        "url": report["url"],
+        "seconds": report["seconds"],
+    });
+
+    let mut stream = UnixStream::connect(CLI_SOCKET_PATH)?;
+    send_length_prefixed_message(&mut stream, request.to_string().as_bytes())?;
+    recv_length_prefixed_message(&mut stream)?;
+    Ok(())
+}
+
 fn main() -> io::Result<()> {
     // Tell the browser extension the bridge process has started.
     write_browser_message(r#"{"status":"starting"}"#)?;
 
+    // Relay messages from the extension. The browser closes stdin when it
+    // disconnects, which is our cue to exit.
+    thread::spawn(|| {
+        while let Ok(message) = read_browser_message() {
+            let Ok(report) = serde_json::from_str::<Value>(&message) else {
+                continue;
+            };
+            if report["type"] == "activity" {
+                let _ = forward_activity(&report);
+            }
+        }
+        std::process::exit(0);
+    });
+
     let mut backoff = Duration::from_millis(100);
     const MAX_BACKOFF: Duration = Duration::from_secs(30);
 
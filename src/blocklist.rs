@@ -0,0 +1,133 @@
+use std::collections::HashSet;
+use std::fs;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+
+// Names that hosts files map to loopback addresses for the system itself.
+const HOSTS_FILE_LOCAL_NAMES: &[&str] = &[
+    "localhost",
+    "localhost.localdomain",
+    "local",
+    "broadcasthost",
+    "ip6-localhost",
+    "ip6-loopback",
+    "ip6-localnet",
+    "ip6-mcastprefix",
+    "ip6-allnodes",
+    "ip6-allrouters",
+    "ip6-allhosts",
+    "0.0.0.0",
+];
+
+/// Expands a leading `~/` and resolves relative paths against `base_dir`.
+pub fn resolve_path(path: &str, base_dir: &Path) -> PathBuf {
+    if let Some(rest) = path.strip_prefix("~/")
+        && let Some(home) = dirs::home_dir()
+    {
+        return home.join(rest);
+    }
+
+    base_dir.join(path)
+}
+
+/// Reads a domain list in either hosts-file format (`0.0.0.0 example.com`)
+/// or one-domain-per-line format. Comments and duplicates are skipped, and
+/// every invalid line is reported with its file and line number.
+pub fn load_domain_file(path: &Path) -> Result<Vec<String>, String> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read blocklist file '{}': {}", path.display(), e))?;
+    parse_domain_list(&contents, &path.display().to_string())
+}
+
+pub fn parse_domain_list(contents: &str, source: &str) -> Result<Vec<String>, String> {
+    let mut domains = Vec::new();
+    let mut seen = HashSet::new();
+    let mut errors = Vec::new();
+
+    for (index, line) in contents.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let mut fields = line.split_whitespace();
+        let first = fields.next().unwrap_or_default();
+        let names: Vec<&str> = if first.parse::<IpAddr>().is_ok() {
+            let names: Vec<&str> = fields.collect();
+            if names.is_empty() {
+                errors.push(format!("{source}:{}: missing host name after '{first}'", index + 1));
+                continue;
+            }
+            names
+                .into_iter()
+                .filter(|name| !HOSTS_FILE_LOCAL_NAMES.contains(name))
+                .collect()
+        } else if let Some(extra) = fields.next() {
+            errors.push(format!(
+                "{source}:{}: expected one domain per line, found '{extra}' after '{first}'",
+                index + 1
+            ));
+            continue;
+        } else {
+            vec![first]
+        };
+
+        for name in names {
+            let domain = name.trim_end_matches('.').to_lowercase();
+            if !is_valid_domain(&domain) {
+                errors.push(format!("{source}:{}: invalid domain '{name}'", index + 1));
+            } else if seen.insert(domain.clone()) {
+                domains.push(domain);
+            }
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(domains)
+    } else {
+        Err(errors.join("\n"))
+    }
+}
+
+fn is_valid_domain(domain: &str) -> bool {
+    domain.contains('.')
+        && domain.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_hosts_and_plain_formats() {
+        let contents = "\
+# StevenBlack-style header
+127.0.0.1 localhost
+0.0.0.0 0.0.0.0
+0.0.0.0 facebook.com www.facebook.com # inline comment
+
+instagram.com
+Facebook.com
+";
+        let domains = parse_domain_list(contents, "social.txt").unwrap();
+        assert_eq!(domains, ["facebook.com", "www.facebook.com", "instagram.com"]);
+    }
+
+    #[test]
+    fn test_invalid_lines_report_file_and_line() {
+        let contents = "good.com\nbad domain.com\n0.0.0.0\nnot_a-domain\n-bad.com\n";
+        let error = parse_domain_list(contents, "list.txt").unwrap_err();
+        assert!(error.contains("list.txt:2: expected one domain per line"));
+        assert!(error.contains("list.txt:3: missing host name"));
+        assert!(error.contains("list.txt:4: invalid domain 'not_a-domain'"));
+        assert!(error.contains("list.txt:5: invalid domain '-bad.com'"));
+    }
+}
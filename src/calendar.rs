@@ -0,0 +1,715 @@
+use crate::timespec;
+use chrono::{
+    DateTime, Datelike, Duration, Local, Months, NaiveDate, NaiveDateTime, NaiveTime, TimeZone,
+    Utc, Weekday,
+};
+use chrono_tz::Tz;
+use ical::parser::ical::component::IcalEvent;
+use ical::property::Property;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// When one calendar event starts and ends.
+pub type Window = (DateTime<Local>, DateTime<Local>);
+
+/// Reads an `.ics` file and returns the windows of its events that overlap
+/// `from..until`, with recurring events expanded. With a `title`, only
+/// events whose summary contains it (ignoring case) are kept.
+pub fn load_windows(
+    path: &Path,
+    title: Option<&str>,
+    from: DateTime<Local>,
+    until: DateTime<Local>,
+) -> Result<Vec<Window>, String> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read calendar '{}': {}", path.display(), e))?;
+    parse_windows(&contents, title, from, until)
+        .map_err(|e| format!("In calendar '{}': {}", path.display(), e))
+}
+
+fn parse_windows(
+    contents: &str,
+    title: Option<&str>,
+    from: DateTime<Local>,
+    until: DateTime<Local>,
+) -> Result<Vec<Window>, String> {
+    let mut events = Vec::new();
+    for calendar in ical::IcalParser::new(contents.as_bytes()) {
+        let calendar = calendar.map_err(|e| e.to_string())?;
+        for event in &calendar.events {
+            events.push(CalendarEvent::parse(event)?);
+        }
+    }
+
+    // Instances of a recurring event that were moved or cancelled on their
+    // own, which replace the instance starting at their RECURRENCE-ID
+    let mut replaced: HashMap<&str, Vec<EventTime>> = HashMap::new();
+    for event in &events {
+        if let Some(id) = event.recurrence_id {
+            replaced.entry(&event.uid).or_default().push(id);
+        }
+    }
+
+    let title = title.map(str::to_lowercase);
+    let mut windows = Vec::new();
+    for event in &events {
+        let matches = title
+            .as_ref()
+            .is_none_or(|title| event.summary.to_lowercase().contains(title));
+        if event.cancelled || !matches {
+            continue;
+        }
+        let skipped: Vec<EventTime> = match event.recurrence_id {
+            Some(_) => Vec::new(),
+            None => event
+                .exdates
+                .iter()
+                .chain(replaced.get(event.uid.as_str()).into_iter().flatten())
+                .copied()
+                .collect(),
+        };
+
+        for start in event.starts(until) {
+            if skipped.iter().any(|time| time.matches(start, event.start.zone)) {
+                continue;
+            }
+            let (Some(start_at), Some(end_at)) = (
+                event.start.zone.resolve(start),
+                event.start.zone.resolve(start + event.length),
+            ) else {
+                continue;
+            };
+            if start_at < end_at && end_at > from && start_at < until {
+                windows.push((start_at, end_at));
+            }
+        }
+    }
+
+    windows.sort();
+    windows.dedup();
+    Ok(windows)
+}
+
+/// The zone a DTSTART, DTEND or EXDATE is written in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Zone {
+    Utc,
+    Named(Tz),
+    /// No zone given: the system's local time.
+    Floating,
+}
+
+impl Zone {
+    fn resolve(self, datetime: NaiveDateTime) -> Option<DateTime<Local>> {
+        match self {
+            Zone::Utc => Some(Utc.from_utc_datetime(&datetime).with_timezone(&Local)),
+            Zone::Named(zone) => {
+                timespec::in_zone(&zone, datetime).map(|at| at.with_timezone(&Local))
+            }
+            Zone::Floating => timespec::to_local(datetime),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct EventTime {
+    datetime: NaiveDateTime,
+    zone: Zone,
+    /// Written as a date (`VALUE=DATE`), so it covers the whole day.
+    all_day: bool,
+}
+
+impl EventTime {
+    /// Parses each of the property's comma-separated values.
+    fn parse_all(property: &Property) -> Result<Vec<EventTime>, String> {
+        let zone = match param(property, "TZID") {
+            Some(name) => Zone::Named(
+                name.trim_start_matches('/')
+                    .parse()
+                    .map_err(|_| format!("{}: unknown time zone '{}'", property.name, name))?,
+            ),
+            None => Zone::Floating,
+        };
+
+        let value = property.value.as_deref().unwrap_or_default();
+        value
+            .split(',')
+            .map(|value| {
+                let invalid = || format!("{}: invalid date or time '{}'", property.name, value);
+                if let Ok(date) = NaiveDate::parse_from_str(value, "%Y%m%d") {
+                    return Ok(EventTime {
+                        datetime: date.and_time(NaiveTime::MIN),
+                        zone: Zone::Floating,
+                        all_day: true,
+                    });
+                }
+                let (value, zone) = match value.strip_suffix('Z') {
+                    Some(utc) => (utc, Zone::Utc),
+                    None => (value, zone),
+                };
+                let datetime = NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S")
+                    .map_err(|_| invalid())?;
+                Ok(EventTime {
+                    datetime,
+                    zone,
+                    all_day: false,
+                })
+            })
+            .collect()
+    }
+
+    fn parse(property: &Property) -> Result<EventTime, String> {
+        match EventTime::parse_all(property)?.as_slice() {
+            [time] => Ok(*time),
+            _ => Err(format!("{}: expected a single date or time", property.name)),
+        }
+    }
+
+    /// Whether this is the instance that starts at `start` in `zone`.
+    fn matches(&self, start: NaiveDateTime, zone: Zone) -> bool {
+        if self.all_day {
+            return self.datetime.date() == start.date();
+        }
+        zone.resolve(start)
+            .is_some_and(|start_at| self.zone.resolve(self.datetime) == Some(start_at))
+    }
+}
+
+#[derive(Debug)]
+struct CalendarEvent {
+    uid: String,
+    summary: String,
+    cancelled: bool,
+    start: EventTime,
+    /// How long each instance lasts, in the start's wall-clock time.
+    length: Duration,
+    rule: Option<Rule>,
+    exdates: Vec<EventTime>,
+    recurrence_id: Option<EventTime>,
+}
+
+impl CalendarEvent {
+    fn parse(event: &IcalEvent) -> Result<CalendarEvent, String> {
+        let property = |name: &str| event.properties.iter().find(|p| p.name == name);
+        let text = |name: &str| property(name).and_then(|p| p.value.as_deref()).map(unescape);
+        let summary = text("SUMMARY").unwrap_or_default();
+        let described = |e: String| format!("event '{summary}': {e}");
+
+        let start = EventTime::parse(property("DTSTART").ok_or("an event has no DTSTART")?)
+            .map_err(described)?;
+        let end = property("DTEND").map(EventTime::parse).transpose().map_err(described)?;
+        let length = match (end, text("DURATION")) {
+            (Some(end), _) if end.zone == start.zone => end.datetime - start.datetime,
+            (Some(end), _) => {
+                let end_at = end.zone.resolve(end.datetime);
+                let start_at = start.zone.resolve(start.datetime);
+                match (end_at, start_at) {
+                    (Some(end_at), Some(start_at)) => end_at - start_at,
+                    _ => Duration::zero(),
+                }
+            }
+            (None, Some(duration)) => parse_duration(&duration).map_err(described)?,
+            (None, None) if start.all_day => Duration::days(1),
+            (None, None) => Duration::zero(),
+        };
+
+        let rule = text("RRULE").map(|rule| Rule::parse(&rule)).transpose().map_err(described)?;
+        let mut exdates = Vec::new();
+        for exdate in event.properties.iter().filter(|p| p.name == "EXDATE") {
+            exdates.extend(EventTime::parse_all(exdate).map_err(described)?);
+        }
+        let recurrence_id = property("RECURRENCE-ID")
+            .map(EventTime::parse)
+            .transpose()
+            .map_err(described)?;
+
+        Ok(CalendarEvent {
+            uid: text("UID").unwrap_or_default(),
+            cancelled: text("STATUS")
+                .is_some_and(|status| status.eq_ignore_ascii_case("CANCELLED")),
+            summary,
+            start,
+            length,
+            rule,
+            exdates,
+            recurrence_id,
+        })
+    }
+
+    /// The wall-clock start of each instance that begins before `until`.
+    fn starts(&self, until: DateTime<Local>) -> Vec<NaiveDateTime> {
+        let first = self.start.datetime;
+        let Some(rule) = &self.rule else {
+            return vec![first];
+        };
+        // Zones are at most 26 hours apart
+        let last_date = until.date_naive() + Duration::days(2);
+
+        let mut starts = Vec::new();
+        for period in 0.. {
+            let Some((period_start, dates)) = rule.period(first.date(), period) else {
+                break;
+            };
+            if period_start > last_date {
+                break;
+            }
+            for date in dates {
+                let start = date.and_time(first.time());
+                if start < first {
+                    continue;
+                }
+                let Some(start_at) = self.start.zone.resolve(start) else {
+                    continue;
+                };
+                let past_until = rule.until.is_some_and(|until| {
+                    if until.all_day {
+                        date > until.datetime.date()
+                    } else {
+                        until.zone.resolve(until.datetime).is_some_and(|until| start_at > until)
+                    }
+                });
+                let counted = rule.count.is_some_and(|count| starts.len() >= count);
+                if past_until || counted || start_at >= until {
+                    return starts;
+                }
+                starts.push(start);
+            }
+        }
+        starts
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Frequency {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+/// An RRULE. BYSETPOS, BYWEEKNO, BYYEARDAY and rules repeating more than
+/// daily aren't supported.
+#[derive(Debug)]
+struct Rule {
+    frequency: Frequency,
+    interval: u32,
+    count: Option<usize>,
+    until: Option<EventTime>,
+    /// Weekdays, each with an optional ordinal such as the 1 in `1MO` (the
+    /// first Monday) or the -1 in `-1FR` (the last Friday).
+    by_day: Vec<(Option<i32>, Weekday)>,
+    by_month_day: Vec<i32>,
+    by_month: Vec<u32>,
+}
+
+impl Rule {
+    fn parse(input: &str) -> Result<Rule, String> {
+        let invalid = |part: &str| format!("invalid RRULE part '{part}'");
+        let mut rule = Rule {
+            frequency: Frequency::Daily,
+            interval: 1,
+            count: None,
+            until: None,
+            by_day: Vec::new(),
+            by_month_day: Vec::new(),
+            by_month: Vec::new(),
+        };
+        let mut frequency = None;
+
+        for part in input.split(';').filter(|part| !part.is_empty()) {
+            let (key, value) = part.split_once('=').ok_or_else(|| invalid(part))?;
+            let numbers = |value: &str| -> Result<Vec<i32>, String> {
+                value.split(',').map(|n| n.parse().map_err(|_| invalid(part))).collect()
+            };
+            match key.to_ascii_uppercase().as_str() {
+                "FREQ" => {
+                    frequency = Some(match value.to_ascii_uppercase().as_str() {
+                        "DAILY" => Frequency::Daily,
+                        "WEEKLY" => Frequency::Weekly,
+                        "MONTHLY" => Frequency::Monthly,
+                        "YEARLY" => Frequency::Yearly,
+                        _ => return Err(format!("unsupported RRULE frequency '{value}'")),
+                    })
+                }
+                "INTERVAL" => {
+                    rule.interval =
+                        value.parse().ok().filter(|n| *n > 0).ok_or_else(|| invalid(part))?
+                }
+                "COUNT" => rule.count = Some(value.parse().map_err(|_| invalid(part))?),
+                "UNTIL" => {
+                    let property = Property {
+                        name: "UNTIL".to_string(),
+                        params: None,
+                        value: Some(value.to_string()),
+                    };
+                    rule.until = Some(EventTime::parse(&property)?);
+                }
+                "BYDAY" => {
+                    for day in value.split(',') {
+                        // Files aren't always ASCII, so don't split inside a character
+                        let (ordinal, weekday) = day
+                            .split_at_checked(day.len().saturating_sub(2))
+                            .ok_or_else(|| invalid(part))?;
+                        let weekday = parse_weekday(weekday).ok_or_else(|| invalid(part))?;
+                        let ordinal = match ordinal {
+                            "" => None,
+                            ordinal => Some(ordinal.parse().map_err(|_| invalid(part))?),
+                        };
+                        rule.by_day.push((ordinal, weekday));
+                    }
+                }
+                "BYMONTHDAY" => rule.by_month_day = numbers(value)?,
+                "BYMONTH" => {
+                    rule.by_month = numbers(value)?
+                        .into_iter()
+                        .map(|month| u32::try_from(month).ok().filter(|m| (1..=12).contains(m)))
+                        .collect::<Option<_>>()
+                        .ok_or_else(|| invalid(part))?
+                }
+                "WKST" => {}
+                _ => return Err(format!("unsupported RRULE part '{part}'")),
+            }
+        }
+
+        rule.frequency = frequency.ok_or("RRULE has no FREQ")?;
+        let ordinals = rule.by_day.iter().any(|(ordinal, _)| ordinal.is_some());
+        match rule.frequency {
+            Frequency::Daily | Frequency::Weekly if ordinals => {
+                Err("numbered BYDAY days need a monthly or yearly RRULE".to_string())
+            }
+            Frequency::Yearly if !rule.by_day.is_empty() && rule.by_month.is_empty() => {
+                Err("BYDAY in a yearly RRULE needs BYMONTH".to_string())
+            }
+            _ => Ok(rule),
+        }
+    }
+
+    /// The first day of the `index`th period (day, week, month or year)
+    /// after the one containing `first`, and the dates in it the rule picks.
+    fn period(&self, first: NaiveDate, index: u32) -> Option<(NaiveDate, Vec<NaiveDate>)> {
+        let steps = index.checked_mul(self.interval)?;
+        let month_start = first.with_day(1)?;
+        let (period_start, mut dates) = match self.frequency {
+            Frequency::Daily => {
+                let date = first.checked_add_days(chrono::Days::new(steps.into()))?;
+                (date, vec![date])
+            }
+            Frequency::Weekly => {
+                let monday = first.week(Weekday::Mon).first_day();
+                let monday = monday.checked_add_days(chrono::Days::new(u64::from(steps) * 7))?;
+                let weekdays = if self.by_day.is_empty() {
+                    vec![first.weekday()]
+                } else {
+                    self.by_day.iter().map(|(_, weekday)| *weekday).collect()
+                };
+                let dates = weekdays
+                    .into_iter()
+                    .map(|weekday| monday + Duration::days(weekday.num_days_from_monday().into()))
+                    .collect();
+                (monday, dates)
+            }
+            Frequency::Monthly => {
+                let month = month_start.checked_add_months(Months::new(steps))?;
+                (month, self.month_dates(month, first.day()))
+            }
+            Frequency::Yearly => {
+                let year = month_start
+                    .with_month(1)?
+                    .checked_add_months(Months::new(steps.checked_mul(12)?))?;
+                let months = if self.by_month.is_empty() {
+                    vec![first.month()]
+                } else {
+                    self.by_month.clone()
+                };
+                let dates = months
+                    .into_iter()
+                    .filter_map(|month| year.with_month(month))
+                    .flat_map(|month| self.month_dates(month, first.day()))
+                    .collect();
+                (year, dates)
+            }
+        };
+
+        dates.retain(|date| {
+            let day_matches = match self.frequency {
+                Frequency::Daily => {
+                    self.by_day.is_empty()
+                        || self.by_day.iter().any(|(_, weekday)| *weekday == date.weekday())
+                }
+                _ => true,
+            };
+            let month_day_matches = self.frequency != Frequency::Daily
+                || self.by_month_day.is_empty()
+                || self.by_month_day.iter().any(|day| month_day(*date, *day) == Some(*date));
+            day_matches
+                && month_day_matches
+                && (self.by_month.is_empty() || self.by_month.contains(&date.month()))
+        });
+        dates.sort();
+        dates.dedup();
+        Some((period_start, dates))
+    }
+
+    /// The dates the rule picks in the month starting on `month`: its
+    /// BYMONTHDAY days, its BYDAY days, or the same day as the first
+    /// instance.
+    fn month_dates(&self, month: NaiveDate, default_day: u32) -> Vec<NaiveDate> {
+        let days_in_month = (month.checked_add_months(Months::new(1)).unwrap_or(month) - month)
+            .num_days() as u32;
+        let all_days = (0..days_in_month).map(|offset| month + Duration::days(offset.into()));
+
+        if !self.by_month_day.is_empty() {
+            return self
+                .by_month_day
+                .iter()
+                .filter_map(|day| month_day(month, *day))
+                .filter(|date| {
+                    self.by_day.is_empty()
+                        || self.by_day.iter().any(|(_, weekday)| *weekday == date.weekday())
+                })
+                .collect();
+        }
+        if self.by_day.is_empty() {
+            return month.with_day(default_day).into_iter().collect();
+        }
+
+        let mut dates = Vec::new();
+        for (ordinal, weekday) in &self.by_day {
+            let matching: Vec<NaiveDate> =
+                all_days.clone().filter(|date| date.weekday() == *weekday).collect();
+            match ordinal {
+                None => dates.extend(matching),
+                Some(n) if *n > 0 => dates.extend(matching.get(*n as usize - 1)),
+                Some(n) => dates.extend(
+                    matching
+                        .len()
+                        .checked_sub(n.unsigned_abs() as usize)
+                        .map(|index| matching[index]),
+                ),
+            }
+        }
+        dates
+    }
+}
+
+/// Day `day` of the month containing `date`, counting back from the end
+/// when negative.
+fn month_day(date: NaiveDate, day: i32) -> Option<NaiveDate> {
+    let month = date.with_day(1)?;
+    let days_in_month = (month.checked_add_months(Months::new(1))? - month).num_days() as i32;
+    let day = if day < 0 { days_in_month + 1 + day } else { day };
+    month.with_day(u32::try_from(day).ok()?)
+}
+
+fn parse_weekday(input: &str) -> Option<Weekday> {
+    Some(match input.to_ascii_uppercase().as_str() {
+        "MO" => Weekday::Mon,
+        "TU" => Weekday::Tue,
+        "WE" => Weekday::Wed,
+        "TH" => Weekday::Thu,
+        "FR" => Weekday::Fri,
+        "SA" => Weekday::Sat,
+        "SU" => Weekday::Sun,
+        _ => return None,
+    })
+}
+
+/// Parses an iCalendar DURATION such as "PT1H30M", "P1D" or "P2W".
+fn parse_duration(input: &str) -> Result<Duration, String> {
+    let invalid = || format!("invalid DURATION '{input}'");
+    let rest = input.strip_prefix('+').unwrap_or(input);
+    let rest = rest.strip_prefix('P').ok_or_else(invalid)?;
+
+    let mut total = Duration::zero();
+    let mut number = String::new();
+    let mut in_time = false;
+    for c in rest.chars() {
+        match c {
+            '0'..='9' => number.push(c),
+            'T' if number.is_empty() => in_time = true,
+            _ => {
+                let n: i64 = number.parse().map_err(|_| invalid())?;
+                number.clear();
+                total += match (c, in_time) {
+                    ('W', false) => Duration::weeks(n),
+                    ('D', false) => Duration::days(n),
+                    ('H', true) => Duration::hours(n),
+                    ('M', true) => Duration::minutes(n),
+                    ('S', true) => Duration::seconds(n),
+                    _ => return Err(invalid()),
+                };
+            }
+        }
+    }
+    if !number.is_empty() || total <= Duration::zero() {
+        return Err(invalid());
+    }
+    Ok(total)
+}
+
+fn param<'a>(property: &'a Property, name: &str) -> Option<&'a str> {
+    property
+        .params
+        .iter()
+        .flatten()
+        .find(|(key, _)| key.eq_ignore_ascii_case(name))
+        .and_then(|(_, values)| values.first())
+        .map(|value| value.trim_matches('"'))
+}
+
+/// Undoes iCalendar text escaping (`\,`, `\;`, `\n` and `\\`).
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some('n' | 'N')) => {
+                chars.next();
+                unescaped.push(' ');
+            }
+            ('\\', Some(escaped @ (',' | ';' | '\\'))) => {
+                chars.next();
+                unescaped.push(escaped);
+            }
+            _ => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utc(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> DateTime<Local> {
+        Utc.with_ymd_and_hms(year, month, day, hour, minute, 0).unwrap().with_timezone(&Local)
+    }
+
+    fn calendar(events: &str) -> String {
+        format!("BEGIN:VCALENDAR\nVERSION:2.0\nPRODID:-//test//EN\n{events}END:VCALENDAR\n")
+    }
+
+    #[test]
+    fn test_events_are_filtered_by_title_and_status() {
+        let contents = calendar(
+            "\
+BEGIN:VEVENT
+UID:1
+SUMMARY:Deep Focus
+DTSTART:20261020T090000Z
+DTEND:20261020T110000Z
+END:VEVENT
+BEGIN:VEVENT
+UID:2
+SUMMARY:Lunch
+DTSTART:20261020T120000Z
+DURATION:PT1H
+END:VEVENT
+BEGIN:VEVENT
+UID:3
+SUMMARY:focus (cancelled)
+STATUS:CANCELLED
+DTSTART:20261021T090000Z
+DTEND:20261021T110000Z
+END:VEVENT
+BEGIN:VEVENT
+UID:4
+SUMMARY:Focus in Berlin
+DTSTART;TZID=Europe/Berlin:20261022T090000
+DTEND;TZID=Europe/Berlin:20261022T100000
+END:VEVENT
+BEGIN:VEVENT
+UID:5
+SUMMARY:Focus day
+DTSTART;VALUE=DATE:20261023
+END:VEVENT
+",
+        );
+        let (from, until) = (utc(2026, 10, 19, 0, 0), utc(2026, 11, 1, 0, 0));
+
+        let windows = parse_windows(&contents, Some("FOCUS"), from, until).unwrap();
+        let all_day = Local.with_ymd_and_hms(2026, 10, 23, 0, 0, 0).unwrap();
+        assert_eq!(
+            windows,
+            [
+                (utc(2026, 10, 20, 9, 0), utc(2026, 10, 20, 11, 0)),
+                (utc(2026, 10, 22, 7, 0), utc(2026, 10, 22, 8, 0)),
+                (all_day, Local.with_ymd_and_hms(2026, 10, 24, 0, 0, 0).unwrap()),
+            ]
+        );
+        assert_eq!(parse_windows(&contents, None, from, until).unwrap().len(), 4);
+        assert!(parse_windows(&contents, Some("focus"), until, until).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_recurring_events_expand_with_exceptions() {
+        let contents = calendar(
+            "\
+BEGIN:VEVENT
+UID:weekly
+SUMMARY:Focus
+DTSTART:20261005T090000Z
+DTEND:20261005T100000Z
+RRULE:FREQ=WEEKLY;BYDAY=MO,WE;COUNT=6
+EXDATE:20261007T090000Z
+END:VEVENT
+BEGIN:VEVENT
+UID:weekly
+SUMMARY:Focus
+RECURRENCE-ID:20261014T090000Z
+DTSTART:20261014T150000Z
+DTEND:20261014T160000Z
+END:VEVENT
+BEGIN:VEVENT
+UID:monthly
+SUMMARY:Focus review
+DTSTART:20260130T130000Z
+DTEND:20260130T140000Z
+RRULE:FREQ=MONTHLY;BYDAY=-1FR;UNTIL=20261231T000000Z
+END:VEVENT
+",
+        );
+        let (from, until) = (utc(2026, 10, 1, 0, 0), utc(2026, 12, 31, 0, 0));
+
+        let starts: Vec<_> = parse_windows(&contents, None, from, until)
+            .unwrap()
+            .into_iter()
+            .map(|(start, _)| start)
+            .collect();
+        assert_eq!(
+            starts,
+            [
+                utc(2026, 10, 5, 9, 0),
+                utc(2026, 10, 12, 9, 0),
+                utc(2026, 10, 14, 15, 0),
+                utc(2026, 10, 19, 9, 0),
+                utc(2026, 10, 21, 9, 0),
+                utc(2026, 10, 30, 13, 0),
+                utc(2026, 11, 27, 13, 0),
+                utc(2026, 12, 25, 13, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unsupported_rules_are_reported() {
+        let contents = calendar(
+            "\
+BEGIN:VEVENT
+SUMMARY:Focus
+DTSTART:20261005T090000Z
+DTEND:20261005T100000Z
+RRULE:FREQ=MONTHLY;BYSETPOS=-1;BYDAY=MO,TU,WE,TH,FR
+END:VEVENT
+",
+        );
+        let now = utc(2026, 10, 1, 0, 0);
+        let error = parse_windows(&contents, None, now, now).unwrap_err();
+        assert!(error.contains("event 'Focus': unsupported RRULE part 'BYSETPOS=-1'"), "{error}");
+
+        let contents = contents.replace("BYSETPOS=-1;BYDAY=MO,TU,WE,TH,FR", "BYDAY=ÖM");
+        let error = parse_windows(&contents, None, now, now).unwrap_err();
+        assert!(error.contains("invalid RRULE part 'BYDAY=ÖM'"), "{error}");
+    }
+}
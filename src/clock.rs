@@ -0,0 +1,54 @@
+use chrono::{DateTime, Local};
+#[cfg(test)]
+use tokio::sync::watch;
+
+/// Where the daemon gets the time from and how it waits for a moment to
+/// come. Everything that schedules, locks or expires goes through it, so
+/// tests can run the daemon on a fake clock that they move forward by hand.
+#[derive(Clone)]
+pub enum Clock {
+    System,
+    /// Only moves when [`Clock::advance`] is called.
+    #[cfg(test)]
+    Fake(watch::Sender<DateTime<Local>>),
+}
+
+impl Clock {
+    #[cfg(test)]
+    pub fn fake(start: DateTime<Local>) -> Clock {
+        Clock::Fake(watch::Sender::new(start))
+    }
+
+    pub fn now(&self) -> DateTime<Local> {
+        match self {
+            Clock::System => Local::now(),
+            #[cfg(test)]
+            Clock::Fake(now) => *now.borrow(),
+        }
+    }
+
+    /// Waits until `at` by this clock.
+    pub async fn sleep_until(&self, at: DateTime<Local>) {
+        match self {
+            Clock::System => {
+                let wait = (at - Local::now()).to_std().unwrap_or_default();
+                tokio::time::sleep(wait).await;
+            }
+            #[cfg(test)]
+            Clock::Fake(now) => {
+                // The sender lives as long as the clock, so this can't fail
+                let _ = now.subscribe().wait_for(|now| *now >= at).await;
+            }
+        }
+    }
+
+    /// Moves a fake clock forward, waking everything that was waiting for
+    /// a moment up to the new time.
+    #[cfg(test)]
+    pub fn advance(&self, by: chrono::Duration) {
+        match self {
+            Clock::System => panic!("the system clock can't be moved"),
+            Clock::Fake(now) => now.send_modify(|now| *now += by),
+        }
+    }
+}
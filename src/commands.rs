@@ -1,3 +1,8 @@
+use crate::apps;
+use crate::config;
+use crate::simulate::{self, Difference, Transition};
+use crate::state::{OneOff, SchedulePause};
+use chrono::{DateTime, Local};
 use serde_json::Value;
 use serde_json::json;
 use shire_blocker::recv_length_prefixed_message;
@@ -36,6 +41,22 @@ pub fn send_action_with_params(
 
     Ok(response)
 }
+/// Prints any message from the daemon, exiting if the request was rejected.
+pub fn report_response(response: &str) {
+    let v: Value = serde_json::from_str(response).unwrap_or_default();
+    if v["status"] == "error" {
+        eprintln!(
+            "{RED}Error:{RESET} {}",
+            v["message"].as_str().unwrap_or("unknown error")
+        );
+        std::process::exit(1);
+    }
+
+    if let Some(message) = v["message"].as_str() {
+        println!("{message}");
+    }
+}
+
 pub fn list_blocks(stream: &mut UnixStream) -> io::Result<()> {
     let response = send_action_with_params(stream, "list_blocks", None)?;
     let v: Value = serde_json::from_str(&response).expect("Invalid JSON");
@@ -61,34 +82,338 @@ fn print_formatted_block_output(response: Value) {
         .max()
         .unwrap_or(10)
         .max("Block Name".len());
-    let status_width = "Status".len();
+    let status_width = "Unblocked".len();
 
     // Print header
     println!(
-        "{:<width1$}  {:<width2$}",
+        "{:<width1$}  {:<width2$}  Details",
         "Block Name",
         "Status",
         width1 = name_width,
         width2 = status_width
     );
     println!(
-        "{:-<width1$}  {:-<width2$}",
+        "{:-<width1$}  {:-<width2$}  {:-<width3$}",
+        "",
         "",
         "",
         width1 = name_width,
-        width2 = status_width
+        width2 = status_width,
+        width3 = "Details".len()
     );
 
     // Print each block with color-coded status
-    for (name, state_val) in blocks {
-        let status_str = state_val.as_str().unwrap_or("Unknown");
+    for (name, info) in blocks {
+        let status_str = info["state"].as_str().unwrap_or("Unknown");
+        let locked = info["locked_for"].is_i64();
+        // Pad before colouring so the escape codes don't break alignment
+        let status_cell = |label: &str| format!("{label:<status_width$}");
         let colored_status = match status_str {
-            "Blocked" => format!("{YELLOW}Blocked{RESET}"),
-            "BlockedWithLock" | "Locked" => format!("{RED}Locked{RESET}"),
-            "Unblocked" => format!("{GREEN}Unblocked{RESET}"),
-            _ => status_str.to_string(),
+            "Blocked" if locked => format!("{RED}{}{RESET}", status_cell("Locked")),
+            "Blocked" => format!("{YELLOW}{}{RESET}", status_cell("Blocked")),
+            "Unblocked" => format!("{GREEN}{}{RESET}", status_cell("Unblocked")),
+            _ => status_cell(status_str),
+        };
+
+        let mut details = Vec::new();
+        let layers: Vec<&str> = info["active_because"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(Value::as_str)
+            .collect();
+        if !layers.is_empty() {
+            details.push(format!("held by {}", layers.join(" + ")));
+        }
+        if let Some(secs) = info["unblock_in"].as_i64() {
+            details.push(format!("ends in {}", format_remaining(secs)));
+        }
+        if let Some(secs) = info["pending_unblock_in"].as_i64() {
+            details.push(format!("unblocking in {}", format_remaining(secs)));
+        }
+        if let Some(secs) = info["locked_for"].as_i64() {
+            details.push(format!("locked for {}", format_remaining(secs)));
+        }
+        match info["allowance_left"].as_i64() {
+            Some(0) => details.push("allowance used up".to_string()),
+            Some(secs) => details.push(format!("{} allowance left", format_remaining(secs))),
+            None => {}
+        }
+        for action in info["applications"].as_array().into_iter().flatten() {
+            let Ok(action) = serde_json::from_value::<apps::Action>(action.clone()) else {
+                continue;
+            };
+            details.push(format!(
+                "{} {} (pid {}) at {}",
+                action.signal.describe(),
+                action.name,
+                action.pid,
+                action.at.format("%H:%M")
+            ));
+        }
+
+        println!(
+            "{:<width1$}  {}  {}",
+            name,
+            colored_status,
+            details.join(", "),
+            width1 = name_width
+        );
+    }
+}
+
+/// Prints the weekly schedules, marking those added with `shire schedule
+/// add`, and any paused blocks.
+pub fn list_schedule(stream: &mut UnixStream) -> io::Result<()> {
+    let response = send_action_with_params(stream, "list_schedule", None)?;
+    let v: Value = serde_json::from_str(&response).expect("Invalid JSON");
+    let schedules = v["schedules"].as_array().cloned().unwrap_or_default();
+
+    if schedules.is_empty() {
+        println!("No schedules are set up.");
+    } else {
+        let rows: Vec<[String; 7]> = schedules
+            .iter()
+            .map(|schedule| {
+                let source = match schedule["id"].as_u64() {
+                    Some(id) => format!("added {id}"),
+                    None => "config".to_string(),
+                };
+                let days: Vec<&str> = schedule["days"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(Value::as_str)
+                    .collect();
+                let text = |key: &str| schedule[key].as_str().unwrap_or_default().to_string();
+                let zone = schedule["timezone"].as_str().unwrap_or("local").to_string();
+                if let Some(cron) = schedule["cron"].as_str() {
+                    let days = format!("cron '{cron}'");
+                    let end = format!("+{}", text("duration"));
+                    return [source, text("block"), days, "-".into(), end, zone, String::new()];
+                }
+                if let Some(ics) = schedule["ics"].as_str() {
+                    let days = match schedule["title"].as_str() {
+                        Some(title) => format!("events '{title}' in {ics}"),
+                        None => format!("events in {ics}"),
+                    };
+                    // Event times are already in local time
+                    let (zone, none) = ("local".to_string(), String::new());
+                    return [source, text("block"), days, "-".into(), "-".into(), zone, none];
+                }
+                let local = match schedule["timezone"].as_str() {
+                    Some(_) => format!("{}-{}", text("local_start"), text("local_end")),
+                    None => String::new(),
+                };
+                [source, text("block"), days.join(", "), text("start"), text("end"), zone, local]
+            })
+            .collect();
+
+        let headers = ["Source", "Block", "Days", "Start", "End", "Zone", "Local time"];
+        let widths: Vec<usize> = (0..headers.len())
+            .map(|i| {
+                rows.iter()
+                    .map(|row| row[i].len())
+                    .max()
+                    .unwrap_or(0)
+                    .max(headers[i].len())
+            })
+            .collect();
+        let pad = |cells: &[String]| -> Vec<String> {
+            cells
+                .iter()
+                .zip(&widths)
+                .map(|(cell, width)| format!("{cell:<width$}"))
+                .collect()
         };
 
-        println!("{:<width1$}  {}", name, colored_status, width1 = name_width);
+        println!("{}", pad(&headers.map(String::from)).join("  ").trim_end());
+        let rules: Vec<String> = widths.iter().map(|width| "-".repeat(*width)).collect();
+        println!("{}", rules.join("  "));
+        for row in &rows {
+            let mut cells = pad(row);
+            // Pad before colouring so the escape codes don't break alignment
+            if row[0] != "config" {
+                cells[0] = format!("{YELLOW}{}{RESET}", cells[0]);
+            }
+            println!("{}", cells.join("  ").trim_end());
+        }
+    }
+
+    let pauses: Vec<SchedulePause> = serde_json::from_value(v["pauses"].clone())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    for pause in pauses {
+        println!(
+            "Paused: {} until {}",
+            pause.block,
+            pause.until.format("%Y-%m-%d %H:%M")
+        );
+    }
+    Ok(())
+}
+
+/// Prints when each block's schedule switches it on and off between `from`
+/// and `to`. With `other_config`, prints only the switches that differ:
+/// `-` for those that happen only with the first config, `+` for those only
+/// with the other.
+pub fn simulate_schedule(
+    config_path: Option<String>,
+    other_config: Option<String>,
+    from: DateTime<Local>,
+    to: DateTime<Local>,
+) -> Result<(), String> {
+    let run = |path: Option<String>| {
+        let config = config::parse_config(path).map_err(|e| format!("Invalid configuration: {e}"))?;
+        simulate::simulate(&config, from, to)
+    };
+    let transitions = run(config_path)?;
+    let Some(other_config) = other_config else {
+        if transitions.is_empty() {
+            println!("No block is switched on or off by its schedule in this range.");
+        }
+        let width = transitions.iter().map(|t| t.block.len()).max().unwrap_or(0);
+        for transition in &transitions {
+            println!("{}", format_transition(transition, width));
+        }
+        return Ok(());
+    };
+
+    let other_transitions = run(Some(other_config))?;
+    let differences = simulate::diff(&transitions, &other_transitions);
+    if differences.is_empty() {
+        println!("Both configs switch blocks on and off at the same times.");
+    }
+    let width = transitions
+        .iter()
+        .chain(&other_transitions)
+        .map(|t| t.block.len())
+        .max()
+        .unwrap_or(0);
+    for difference in differences {
+        match difference {
+            Difference::Removed(t) => println!("{RED}- {}{RESET}", format_transition(t, width)),
+            Difference::Added(t) => println!("{GREEN}+ {}{RESET}", format_transition(t, width)),
+        }
+    }
+    Ok(())
+}
+
+fn format_transition(transition: &Transition, width: usize) -> String {
+    let state = if transition.active { "on" } else { "off" };
+    format!(
+        "{}  {:<width$}  {state}",
+        transition.at.format("%a %Y-%m-%d %H:%M %:z"),
+        transition.block
+    )
+}
+
+/// Prints the block starts booked with `shire block start --at`.
+pub fn list_one_offs(stream: &mut UnixStream) -> io::Result<()> {
+    let response = send_action_with_params(stream, "list_one_offs", None)?;
+    let v: Value = serde_json::from_str(&response).expect("Invalid JSON");
+    let one_offs: Vec<OneOff> = serde_json::from_value(v["one_offs"].clone())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+    if one_offs.is_empty() {
+        println!("No block starts are pending.");
+        return Ok(());
+    }
+
+    let block_width = one_offs
+        .iter()
+        .map(|one_off| one_off.block.len())
+        .max()
+        .unwrap_or(0)
+        .max("Block".len());
+    println!("{:<4}  {:<block_width$}  {:<16}  Ends", "ID", "Block", "Starts");
+    println!("{:-<4}  {:-<block_width$}  {:-<16}  {:-<4}", "", "", "", "");
+    for one_off in one_offs {
+        let mut ends = one_off
+            .until
+            .map(|until| until.format("%Y-%m-%d %H:%M").to_string())
+            .unwrap_or_else(|| "-".to_string());
+        if let Some(lock_until) = one_off.lock_until {
+            ends.push_str(&format!(" {RED}(locked until {}){RESET}", lock_until.format("%H:%M")));
+        }
+        println!(
+            "{:<4}  {:<block_width$}  {}  {ends}",
+            one_off.id,
+            one_off.block,
+            one_off.at.format("%Y-%m-%d %H:%M"),
+        );
+    }
+    Ok(())
+}
+
+pub fn pomodoro_status(stream: &mut UnixStream) -> io::Result<()> {
+    let response = send_action_with_params(stream, "pomodoro_status", None)?;
+    let v: Value = serde_json::from_str(&response).expect("Invalid JSON");
+
+    if v["status"] != "running" {
+        println!("No pomodoro is running.");
+        return Ok(());
+    }
+
+    let blocks: Vec<&str> = v["blocks"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_str)
+        .collect();
+    let phase = match v["phase"].as_str() {
+        Some("Work") => format!("{YELLOW}Work{RESET}"),
+        Some("Break") => format!("{GREEN}Break{RESET}"),
+        _ => "Unknown".to_string(),
+    };
+
+    println!(
+        "{phase} {}/{}, {} left (blocks: {})",
+        v["cycle"],
+        v["cycles"],
+        format_remaining(v["remaining"].as_i64().unwrap_or(0)),
+        blocks.join(", ")
+    );
+    Ok(())
+}
+
+pub fn session_status(stream: &mut UnixStream) -> io::Result<()> {
+    let response = send_action_with_params(stream, "session_status", None)?;
+    let v: Value = serde_json::from_str(&response).expect("Invalid JSON");
+
+    if v["status"] != "running" {
+        println!("No session is running.");
+        return Ok(());
+    }
+
+    let blocks: Vec<&str> = v["blocks"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_str)
+        .collect();
+    let lock_note = if v["locked"] == true {
+        format!(" {RED}(locked){RESET}")
+    } else {
+        String::new()
+    };
+
+    println!(
+        "Session '{}'{lock_note}: {} left",
+        v["name"].as_str().unwrap_or_default(),
+        format_remaining(v["remaining"].as_i64().unwrap_or(0))
+    );
+    println!("Blocks: {}", blocks.join(", "));
+    Ok(())
+}
+
+/// Formats a number of seconds as e.g. "1h 29m", "12m" or "40s".
+fn format_remaining(secs: i64) -> String {
+    let hours = secs / 3600;
+    let minutes = (secs % 3600) / 60;
+    match (hours, minutes) {
+        (0, 0) => format!("{secs}s"),
+        (0, m) => format!("{m}m"),
+        (h, 0) => format!("{h}h"),
+        (h, m) => format!("{h}h {m}m"),
     }
 }
@@ -1,44 +1,750 @@
+use crate::apps::{self, ApplicationAction};
+use crate::blocklist;
+use crate::private;
+use crate::timespec::{
+    parse_cron, parse_date, parse_days, parse_duration, parse_time_of_day, parse_timezone,
+};
+use chrono::{DateTime, Duration, Local, NaiveDate, Offset, Timelike, Weekday};
+use chrono_tz::Tz;
+use croner::Cron;
 use serde::Deserialize;
 use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::env;
 use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
 
+/// The config structs reject unknown keys, so a typo stops the daemon
+/// instead of being ignored. `shire config validate` checks keys against
+/// the same fields, see [`field_names`].
 #[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct Config {
+    #[serde(default)]
     pub blocks: Vec<Block>,
+    #[serde(default)]
     pub schedule: Vec<Schedule>,
+    #[serde(default)]
+    pub sessions: Vec<Session>,
+    /// Shared pattern lists that blocks can `include` by name.
+    #[serde(default)]
+    pub lists: BTreeMap<String, PatternList>,
+    #[serde(default)]
+    pub exceptions: Vec<Exception>,
+    /// IANA time zone ("Europe/Berlin") for schedules without their own.
+    /// Copied into those schedules by `parse_config`.
+    pub timezone: Option<String>,
+}
+
+#[derive(PartialEq, Eq, Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct PatternList {
+    pub whitelist: Option<Vec<String>>,
+    pub blacklist: Option<Vec<String>>,
 }
 
 #[derive(PartialEq, Eq, Hash, Debug, Serialize, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct Block {
     pub name: String,
     pub active_by_default: Option<bool>,
     pub whitelist: Option<Vec<String>>,
     pub blacklist: Option<Vec<String>>,
+    /// How long a manual stop waits before taking effect, e.g. "15m".
+    pub unblock_delay: Option<String>,
+    /// How long matching sites may be used each day, e.g. "20m".
+    pub daily_allowance: Option<String>,
+    /// Executable names or command lines whose processes are stopped while
+    /// the block is active.
+    pub applications: Option<Vec<String>>,
+    /// "terminate" (the default) or "suspend".
+    pub application_action: Option<String>,
+    /// Other blocks or `[lists.*]` whose patterns this block also uses.
+    /// Resolved into `whitelist` and `blacklist` by `parse_config`.
+    pub include: Option<Vec<String>>,
+    /// Hosts files or domain lists loaded into `whitelist` and `blacklist`.
+    /// Relative paths are resolved against the config file's directory.
+    pub whitelist_files: Option<Vec<String>>,
+    pub blacklist_files: Option<Vec<String>>,
+    /// The name shown in place of the real one for blocks defined in an
+    /// encrypted fragment. Written there as `alias`, but only set while
+    /// loading the fragment.
+    #[serde(skip_deserializing)]
+    pub alias: Option<String>,
+    /// Set for blocks from an encrypted fragment, whose `name` has already
+    /// been replaced by their alias.
+    #[serde(skip_deserializing)]
+    pub private: bool,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(deny_unknown_fields)]
 pub struct Schedule {
+    /// Lets `[[exceptions]]` refer to this schedule.
+    pub name: Option<String>,
     pub block: String,
+    #[serde(default)]
     pub days: Vec<String>,
+    #[serde(default)]
     pub start: String,
+    #[serde(default)]
     pub end: String,
+    /// Instead of `days`, `start` and `end`: a cron expression for when each
+    /// window starts, and how long it lasts.
+    pub cron: Option<String>,
+    pub duration: Option<String>,
+    /// Or a calendar file, whose events (those with `title` in their name,
+    /// if set) are the windows.
+    pub ics: Option<String>,
+    pub title: Option<String>,
+    /// First and last dates ("2026-05-01") the schedule applies, inclusive.
+    pub valid_from: Option<String>,
+    pub valid_until: Option<String>,
+    /// IANA time zone the days and times are in, instead of the system's.
+    pub timezone: Option<String>,
+}
+
+/// Dates on which schedules are suspended, such as a public holiday or a
+/// vacation. Without `blocks` or `schedules` it suspends every schedule.
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Exception {
+    /// A single date ("2026-12-25"), or use `from` and `until` instead.
+    pub date: Option<String>,
+    pub from: Option<String>,
+    pub until: Option<String>,
+    /// Suspend only the schedules of these blocks.
+    pub blocks: Option<Vec<String>>,
+    /// Suspend only the schedules with these names.
+    pub schedules: Option<Vec<String>>,
+}
+
+impl Exception {
+    /// The first and last suspended dates, inclusive.
+    pub fn date_range(&self) -> Result<(NaiveDate, NaiveDate), String> {
+        let (first, last) = match (&self.date, &self.from, &self.until) {
+            (Some(date), None, None) => (parse_date(date)?, parse_date(date)?),
+            (None, Some(from), Some(until)) => (parse_date(from)?, parse_date(until)?),
+            _ => return Err("set either `date`, or both `from` and `until`".to_string()),
+        };
+        if last < first {
+            return Err(format!("`until` ({last}) is before `from` ({first})"));
+        }
+        Ok((first, last))
+    }
+
+    pub fn applies_to(&self, schedule: &Schedule) -> bool {
+        if self.blocks.is_none() && self.schedules.is_none() {
+            return true;
+        }
+        self.blocks.iter().flatten().any(|block| *block == schedule.block)
+            || schedule
+                .name
+                .as_ref()
+                .is_some_and(|name| self.schedules.iter().flatten().any(|s| s == name))
+    }
+}
+
+impl Schedule {
+    /// The distinct days this schedule runs on, Monday first.
+    pub fn weekdays(&self) -> Result<Vec<Weekday>, String> {
+        let mut weekdays = Vec::new();
+        for day in &self.days {
+            weekdays.extend(parse_days(day)?);
+        }
+        weekdays.sort_by_key(|day| day.num_days_from_monday());
+        weekdays.dedup();
+        Ok(weekdays)
+    }
+
+    /// The `valid_from` and `valid_until` dates, if set.
+    pub fn validity(&self) -> Result<(Option<NaiveDate>, Option<NaiveDate>), String> {
+        let from = self.valid_from.as_deref().map(parse_date).transpose()?;
+        let until = self.valid_until.as_deref().map(parse_date).transpose()?;
+        if let (Some(from), Some(until)) = (from, until)
+            && until < from
+        {
+            return Err(format!("valid_until ({until}) is before valid_from ({from})"));
+        }
+        Ok((from, until))
+    }
+
+    /// The schedule's time zone, or None for the system's local time.
+    pub fn zone(&self) -> Result<Option<Tz>, String> {
+        self.timezone.as_deref().map(parse_timezone).transpose()
+    }
+
+    /// The cron expression and window length of a schedule written with
+    /// `cron`, or None for the other kinds.
+    pub fn cron_window(&self) -> Result<Option<(Cron, Duration)>, String> {
+        self.check_form()?;
+        match (&self.cron, &self.duration) {
+            (None, None) => Ok(None),
+            (Some(_), None) => Err("a `cron` schedule needs a `duration`".to_string()),
+            (None, Some(_)) => Err("`duration` only applies to `cron` schedules".to_string()),
+            (Some(cron), Some(duration)) => Ok(Some((
+                parse_cron(cron)?,
+                parse_window_duration(duration)?,
+            ))),
+        }
+    }
+
+    /// The calendar file and title filter of a schedule written with `ics`,
+    /// or None for the other kinds.
+    pub fn calendar(&self) -> Result<Option<(&Path, Option<&str>)>, String> {
+        self.check_form()?;
+        match (&self.ics, &self.title) {
+            (None, None) => Ok(None),
+            (None, Some(_)) => Err("`title` only applies to `ics` schedules".to_string()),
+            (Some(ics), title) => Ok(Some((Path::new(ics), title.as_deref()))),
+        }
+    }
+
+    /// A schedule is written with one of `days`, `start` and `end`; `cron`
+    /// and `duration`; or `ics`.
+    fn check_form(&self) -> Result<(), String> {
+        let weekly = !self.days.is_empty() || !self.start.is_empty() || !self.end.is_empty();
+        let cron = self.cron.is_some() || self.duration.is_some();
+        let forms = [weekly, cron, self.ics.is_some()];
+        if forms.into_iter().filter(|used| *used).count() > 1 {
+            return Err(
+                "use only one of `days`, `start` and `end`; `cron` and `duration`; or `ics`"
+                    .to_string(),
+            );
+        }
+        Ok(())
+    }
+
+    /// Short description for messages, e.g. "Mon-Fri 9:00-17:00".
+    pub fn describe(&self) -> String {
+        match (&self.cron, &self.duration, &self.ics) {
+            (Some(cron), Some(duration), _) => format!("cron '{cron}' for {duration}"),
+            (_, _, Some(ics)) => match &self.title {
+                Some(title) => format!("events titled '{title}' in {ics}"),
+                None => format!("events in {ics}"),
+            },
+            _ => format!("{} {}-{}", self.days.join(", "), self.start, self.end),
+        }
+    }
+
+    /// Each window as minutes from Monday 00:00 UTC and its length, using
+    /// the zone's offset at `now`. None for cron and calendar schedules,
+    /// whose windows don't repeat weekly.
+    fn weekly_windows(&self, now: DateTime<Local>) -> Option<Vec<(i64, i64)>> {
+        if self.cron.is_some() || self.ics.is_some() {
+            return None;
+        }
+        let minutes = |time: &str| {
+            parse_time_of_day(time).map(|t| i64::from(t.num_seconds_from_midnight() / 60))
+        };
+        let (start, end) = (minutes(&self.start).ok()?, minutes(&self.end).ok()?);
+        let length = if end <= start { end + MINUTES_PER_DAY - start } else { end - start };
+        let offset_secs = match self.zone().ok()? {
+            Some(zone) => now.with_timezone(&zone).offset().fix().local_minus_utc(),
+            None => now.offset().local_minus_utc(),
+        };
+
+        Some(
+            self.weekdays()
+                .ok()?
+                .into_iter()
+                .map(|day| {
+                    let local = i64::from(day.num_days_from_monday()) * MINUTES_PER_DAY + start;
+                    ((local - i64::from(offset_secs) / 60).rem_euclid(MINUTES_PER_WEEK), length)
+                })
+                .collect(),
+        )
+    }
+
+    /// Whether the valid_from/valid_until ranges of two schedules share a day.
+    fn valid_together(&self, other: &Schedule) -> bool {
+        let (Ok((from, until)), Ok((other_from, other_until))) = (self.validity(), other.validity())
+        else {
+            return true;
+        };
+        !(until.zip(other_from).is_some_and(|(until, from)| until < from)
+            || other_until.zip(from).is_some_and(|(until, from)| until < from))
+    }
+}
+
+/// Parses how long each window of a cron schedule lasts: at least a minute,
+/// and at most a day.
+pub fn parse_window_duration(input: &str) -> Result<Duration, String> {
+    let duration = parse_duration(input)?;
+    if duration < Duration::minutes(1) || duration > Duration::days(1) {
+        return Err(format!(
+            "Invalid duration '{input}': must be between 1 minute and 24 hours"
+        ));
+    }
+    Ok(duration)
+}
+
+const MINUTES_PER_DAY: i64 = 24 * 60;
+const MINUTES_PER_WEEK: i64 = 7 * MINUTES_PER_DAY;
+
+/// Pairs of schedules (by index) for the same block whose windows overlap.
+/// The daemon keeps the block active until all open windows have ended, but
+/// an overlap is usually a mistake. Schedules in different zones are
+/// compared at their offsets at `now`.
+pub fn schedule_overlaps(schedules: &[Schedule], now: DateTime<Local>) -> Vec<(usize, usize)> {
+    let windows: Vec<_> = schedules.iter().map(|schedule| schedule.weekly_windows(now)).collect();
+    let overlap = |(a, a_len): (i64, i64), (b, b_len): (i64, i64)| {
+        // Windows can wrap past the end of the week
+        [-MINUTES_PER_WEEK, 0, MINUTES_PER_WEEK]
+            .iter()
+            .any(|shift| a < b + shift + b_len && b + shift < a + a_len)
+    };
+
+    let mut pairs = Vec::new();
+    for (i, first) in schedules.iter().enumerate() {
+        for (j, second) in schedules.iter().enumerate().skip(i + 1) {
+            if first.block != second.block || !first.valid_together(second) {
+                continue;
+            }
+            if let (Some(a), Some(b)) = (&windows[i], &windows[j])
+                && a.iter().any(|&x| b.iter().any(|&y| overlap(x, y)))
+            {
+                pairs.push((i, j));
+            }
+        }
+    }
+    pairs
+}
+
+/// The keys a config struct accepts, as listed by its `Deserialize` impl.
+pub fn field_names<T: for<'de> Deserialize<'de>>() -> &'static [&'static str] {
+    use serde::de::{self, Visitor};
+
+    /// Fails every request, except that it records the field names first.
+    struct Recorder<'a>(&'a mut &'static [&'static str]);
+
+    impl<'de> de::Deserializer<'de> for Recorder<'_> {
+        type Error = de::value::Error;
+
+        fn deserialize_any<V: Visitor<'de>>(self, _: V) -> Result<V::Value, Self::Error> {
+            Err(de::Error::custom("not a struct"))
+        }
+
+        fn deserialize_struct<V: Visitor<'de>>(
+            self,
+            _: &'static str,
+            fields: &'static [&'static str],
+            _: V,
+        ) -> Result<V::Value, Self::Error> {
+            *self.0 = fields;
+            Err(de::Error::custom("only the field names are needed"))
+        }
+
+        serde::forward_to_deserialize_any! {
+            bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes
+            byte_buf option unit unit_struct newtype_struct seq tuple tuple_struct map
+            enum identifier ignored_any
+        }
+    }
+
+    let mut fields: &'static [&'static str] = &[];
+    let _ = T::deserialize(Recorder(&mut fields));
+    fields
+}
+
+/// A named set of blocks started together with `shire session start`.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Session {
+    pub name: String,
+    pub blocks: Vec<String>,
+    /// How long the session runs unless overridden on the command line.
+    pub duration: String,
+    /// Whether the blocks stay locked until the session ends.
+    pub lock: Option<bool>,
+}
+
+/// Where the user's config file lives: `$XDG_CONFIG_HOME/shire/shire.toml`,
+/// or `~/.config/shire/shire.toml` when `XDG_CONFIG_HOME` isn't set.
+pub fn user_config_path() -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let config_home = match env::var_os("XDG_CONFIG_HOME").map(PathBuf::from) {
+        Some(dir) if dir.is_absolute() => dir,
+        _ => PathBuf::from(env::var("HOME")?).join(".config"),
+    };
+    Ok(config_home.join("shire").join("shire.toml"))
+}
+
+/// The config file used when no `--config` path is given: the user's own
+/// file if it exists, otherwise the first `shire/shire.toml` found in
+/// `$XDG_CONFIG_DIRS` (default `/etc/xdg`).
+pub fn default_config_path() -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let user_path = user_config_path()?;
+    if user_path.exists() {
+        return Ok(user_path);
+    }
+
+    let system_dirs = env::var("XDG_CONFIG_DIRS")
+        .ok()
+        .filter(|dirs| !dirs.is_empty())
+        .unwrap_or_else(|| "/etc/xdg".to_string());
+    Ok(env::split_paths(&system_dirs)
+        .filter(|dir| dir.is_absolute())
+        .map(|dir| dir.join("shire").join("shire.toml"))
+        .find(|path| path.exists())
+        .unwrap_or(user_path))
+}
+
+/// The `conf.d/*.toml` and encrypted `conf.d/*.toml.enc` fragments next to
+/// a config file, in file name order.
+pub fn fragment_paths(config_path: &Path) -> Result<Vec<PathBuf>, String> {
+    let dir = config_path
+        .parent()
+        .unwrap_or(Path::new("."))
+        .join("conf.d");
+    let entries = match fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(format!("Failed to read '{}': {}", dir.display(), e)),
+    };
+
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            path.is_file() && (name.ends_with(".toml") || name.ends_with(".toml.enc"))
+        })
+        .collect();
+    paths.sort();
+    Ok(paths)
+}
+
+/// A commented example config written by `shire config init`.
+pub const STARTER_CONFIG: &str = include_str!("starter_config.toml");
+
+/// Writes `STARTER_CONFIG` to `path`, creating its directory. An existing
+/// file is only replaced when `force` is set.
+pub fn write_starter_config(path: &Path, force: bool) -> Result<(), String> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)
+            .map_err(|e| format!("Failed to create '{}': {}", dir.display(), e))?;
+    }
+
+    let file = if force {
+        fs::File::create(path)
+    } else {
+        fs::File::create_new(path)
+    };
+    let mut file = file.map_err(|e| match e.kind() {
+        io::ErrorKind::AlreadyExists => format!(
+            "'{}' already exists. Use --force to overwrite it.",
+            path.display()
+        ),
+        _ => format!("Failed to write '{}': {}", path.display(), e),
+    })?;
+
+    file.write_all(STARTER_CONFIG.as_bytes())
+        .map_err(|e| format!("Failed to write '{}': {}", path.display(), e))
 }
 
 pub fn parse_config(config_path: Option<String>) -> Result<Config, Box<dyn std::error::Error>> {
     let path = match config_path {
-        Some(custom_path) => custom_path,
-        None => format!("{}/.config/shire/shire.toml", std::env::var("HOME")?),
+        Some(custom_path) => PathBuf::from(custom_path),
+        None => default_config_path()?,
     };
 
-    let contents = fs::read_to_string(path)?;
-    let config: Config = toml::from_str(&contents)?;
+    let contents = fs::read_to_string(&path)?;
+    let base_dir = path.parent().unwrap_or(Path::new("."));
+    let mut table: toml::Table = toml::from_str(&contents)?;
+
+    let mut private_blocks = HashMap::new();
+    for fragment_path in fragment_paths(&path)? {
+        let contents = read_fragment(&fragment_path, base_dir)?;
+        let mut fragment: toml::Table = toml::from_str(&contents)
+            .map_err(|e| format!("In '{}': {}", fragment_path.display(), e))?;
+        if private::is_encrypted(&fragment_path) {
+            hide_private_names(&mut fragment, &mut private_blocks);
+        }
+        rebase_pattern_files(&mut fragment, fragment_path.parent().unwrap_or(base_dir));
+        merge_tables(&mut table, fragment);
+    }
 
+    parse_config_table(table, base_dir, &private_blocks)
+}
+
+#[cfg(test)]
+pub fn parse_config_str(
+    contents: &str,
+    base_dir: &Path,
+) -> Result<Config, Box<dyn std::error::Error>> {
+    parse_config_table(toml::from_str(contents)?, base_dir, &HashMap::new())
+}
+
+/// Loads the merged config. `private_blocks` maps the names of blocks from
+/// encrypted fragments, already hidden, to their alias if they had one.
+fn parse_config_table(
+    table: toml::Table,
+    base_dir: &Path,
+    private_blocks: &HashMap<String, Option<String>>,
+) -> Result<Config, Box<dyn std::error::Error>> {
+    let mut config: Config = toml::Value::Table(table).try_into()?;
+    for block in &mut config.blocks {
+        if let Some(alias) = private_blocks.get(&block.name) {
+            block.alias = alias.clone();
+            block.private = true;
+        }
+    }
+
+    validate_unique_block_names(&config)?;
+    load_pattern_files(&mut config, base_dir)?;
+    resolve_calendar_paths(&mut config, base_dir);
+    resolve_includes(&mut config)?;
     validate_blocks_exist(&config)?;
+    apply_default_timezone(&mut config)?;
     validate_schedule_times(&config)?;
+    validate_block_durations(&config)?;
+    validate_sessions(&config)?;
+    validate_exceptions(&config)?;
 
     Ok(config)
 }
 
+/// Merges a `conf.d` fragment into the main config: tables are merged key
+/// by key, arrays (blocks, schedules, patterns) are appended, and any other
+/// value in the fragment replaces the one before it.
+fn merge_tables(target: &mut toml::Table, fragment: toml::Table) {
+    for (key, value) in fragment {
+        match (target.get_mut(&key), value) {
+            (Some(toml::Value::Table(existing)), toml::Value::Table(table)) => {
+                merge_tables(existing, table)
+            }
+            (Some(toml::Value::Array(existing)), toml::Value::Array(array)) => {
+                existing.extend(array)
+            }
+            (_, value) => {
+                target.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Reads a fragment, decrypting it with the key from `config_dir` if it's
+/// an encrypted one.
+pub fn read_fragment(path: &Path, config_dir: &Path) -> Result<String, String> {
+    if private::is_encrypted(path) {
+        private::read_encrypted_file(path, config_dir)
+    } else {
+        fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read config file '{}': {}", path.display(), e))
+    }
+}
+
+/// Renames the blocks of an encrypted fragment to their alias (or
+/// "private-N") and updates the fragment's own references to them, so the
+/// real names never reach the daemon, `shire block list` or the logs. Adds
+/// the hidden names to `private_blocks`, with the alias if there was one.
+fn hide_private_names(
+    fragment: &mut toml::Table,
+    private_blocks: &mut HashMap<String, Option<String>>,
+) {
+    let mut aliases = HashMap::new();
+    if let Some(toml::Value::Array(blocks)) = fragment.get_mut("blocks") {
+        for block in blocks.iter_mut().filter_map(|block| block.as_table_mut()) {
+            let (shown, alias) = match block.remove("alias") {
+                Some(toml::Value::String(alias)) => (alias.clone(), Some(alias)),
+                _ => (format!("private-{}", private_blocks.len() + 1), None),
+            };
+            if let Some(toml::Value::String(name)) = block.get("name") {
+                aliases.insert(name.clone(), shown.clone());
+            }
+            block.insert("name".to_string(), toml::Value::String(shown.clone()));
+            private_blocks.insert(shown, alias);
+        }
+    }
+
+    let rename = |value: &mut toml::Value| {
+        if let toml::Value::String(name) = value
+            && let Some(alias) = aliases.get(name.as_str())
+        {
+            *name = alias.clone();
+        }
+    };
+    let references = [
+        ("blocks", "include"),
+        ("schedule", "block"),
+        ("sessions", "blocks"),
+        ("exceptions", "blocks"),
+    ];
+    for (section, key) in references {
+        let Some(toml::Value::Array(entries)) = fragment.get_mut(section) else {
+            continue;
+        };
+        for entry in entries.iter_mut().filter_map(|entry| entry.as_table_mut()) {
+            match entry.get_mut(key) {
+                Some(toml::Value::Array(names)) => names.iter_mut().for_each(rename),
+                Some(value) => rename(value),
+                None => {}
+            }
+        }
+    }
+}
+
+/// Pattern files and calendars in a fragment are relative to the fragment,
+/// not to the main config file, so make them absolute before merging.
+fn rebase_pattern_files(fragment: &mut toml::Table, fragment_dir: &Path) {
+    if let Some(toml::Value::Array(schedules)) = fragment.get_mut("schedule") {
+        for schedule in schedules.iter_mut().filter_map(|schedule| schedule.as_table_mut()) {
+            if let Some(toml::Value::String(path)) = schedule.get_mut("ics") {
+                *path = blocklist::resolve_path(path, fragment_dir).display().to_string();
+            }
+        }
+    }
+    let Some(toml::Value::Array(blocks)) = fragment.get_mut("blocks") else {
+        return;
+    };
+
+    for block in blocks.iter_mut().filter_map(|block| block.as_table_mut()) {
+        for key in ["whitelist_files", "blacklist_files"] {
+            let Some(toml::Value::Array(files)) = block.get_mut(key) else {
+                continue;
+            };
+            for file in files.iter_mut() {
+                if let toml::Value::String(path) = file {
+                    *path = blocklist::resolve_path(path, fragment_dir)
+                        .display()
+                        .to_string();
+                }
+            }
+        }
+    }
+}
+
+fn load_pattern_files(config: &mut Config, base_dir: &Path) -> Result<(), String> {
+    for block in &mut config.blocks {
+        for (files, patterns) in [
+            (&block.whitelist_files, &mut block.whitelist),
+            (&block.blacklist_files, &mut block.blacklist),
+        ] {
+            for file in files.iter().flatten() {
+                let path = blocklist::resolve_path(file, base_dir);
+                let domains = blocklist::load_domain_file(&path)
+                    .map_err(|e| format!("In block '{}':\n{}", block.name, e))?;
+                merge_unique(patterns, &domains);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Makes each schedule's `ics` path absolute, so the daemon can re-read
+/// the calendar wherever it runs from.
+fn resolve_calendar_paths(config: &mut Config, base_dir: &Path) {
+    for schedule in &mut config.schedule {
+        if let Some(ics) = &mut schedule.ics {
+            *ics = blocklist::resolve_path(ics, base_dir).display().to_string();
+        }
+    }
+}
+
+/// Flattens every block's `include`s into its own whitelist and blacklist,
+/// so the rest of the program only ever sees fully resolved blocks.
+fn resolve_includes(config: &mut Config) -> Result<(), Box<dyn std::error::Error>> {
+    let originals: BTreeMap<String, Block> = config
+        .blocks
+        .iter()
+        .map(|block| (block.name.clone(), block.clone()))
+        .collect();
+
+    if let Some(name) = config
+        .lists
+        .keys()
+        .find(|name| originals.contains_key(*name))
+    {
+        return Err(format!("'{name}' is defined both as a block and as a list.").into());
+    }
+
+    for block in &mut config.blocks {
+        let mut resolved = PatternList::default();
+        let mut path = Vec::new();
+        collect_patterns(
+            &block.name,
+            &originals,
+            &config.lists,
+            &mut path,
+            &mut resolved,
+        )?;
+        block.whitelist = resolved.whitelist;
+        block.blacklist = resolved.blacklist;
+    }
+
+    Ok(())
+}
+
+fn collect_patterns(
+    block_name: &str,
+    blocks: &BTreeMap<String, Block>,
+    lists: &BTreeMap<String, PatternList>,
+    path: &mut Vec<String>,
+    resolved: &mut PatternList,
+) -> Result<(), String> {
+    if let Some(start) = path.iter().position(|name| name == block_name) {
+        let mut cycle = path[start..].to_vec();
+        cycle.push(block_name.to_string());
+        return Err(format!("Block include cycle: {}.", cycle.join(" -> ")));
+    }
+
+    let block = &blocks[block_name];
+    append_patterns(resolved, block.whitelist.as_ref(), block.blacklist.as_ref());
+
+    path.push(block_name.to_string());
+    for include in block.include.iter().flatten() {
+        if let Some(list) = lists.get(include) {
+            append_patterns(resolved, list.whitelist.as_ref(), list.blacklist.as_ref());
+        } else if blocks.contains_key(include) {
+            collect_patterns(include, blocks, lists, path, resolved)?;
+        } else {
+            return Err(format!(
+                "Block '{block_name}' includes unknown block or list: '{include}'."
+            ));
+        }
+    }
+    path.pop();
+
+    Ok(())
+}
+
+fn append_patterns(
+    resolved: &mut PatternList,
+    whitelist: Option<&Vec<String>>,
+    blacklist: Option<&Vec<String>>,
+) {
+    if let Some(whitelist) = whitelist {
+        merge_unique(&mut resolved.whitelist, whitelist);
+    }
+    if let Some(blacklist) = blacklist {
+        merge_unique(&mut resolved.blacklist, blacklist);
+    }
+}
+
+/// Appends the patterns from `source` that `target` doesn't already have.
+fn merge_unique(target: &mut Option<Vec<String>>, source: &[String]) {
+    let target = target.get_or_insert_with(Vec::new);
+    // Imported lists can hold thousands of entries, so avoid Vec::contains
+    let mut seen: HashSet<String> = target.iter().cloned().collect();
+    for pattern in source {
+        if seen.insert(pattern.clone()) {
+            target.push(pattern.clone());
+        }
+    }
+}
+
+fn validate_unique_block_names(config: &Config) -> Result<(), String> {
+    let mut names = HashSet::new();
+    for block in &config.blocks {
+        if !names.insert(&block.name) {
+            return Err(format!("Duplicate block name: '{}'.", block.name));
+        }
+    }
+
+    Ok(())
+}
+
 fn validate_blocks_exist(config: &Config) -> Result<(), Box<dyn std::error::Error>> {
     let block_names: std::collections::HashSet<&String> =
         config.blocks.iter().map(|b| &b.name).collect();
@@ -56,100 +762,538 @@ fn validate_blocks_exist(config: &Config) -> Result<(), Box<dyn std::error::Erro
     Ok(())
 }
 
-fn validate_day(day: &str) -> Result<(), String> {
-    let valid_days = ["mon", "tue", "wed", "thu", "fri", "sat", "sun"];
-    let day_lower = day.to_lowercase();
-
-    if !valid_days.contains(&day_lower.as_str()) {
-        return Err(format!(
-            "Invalid day: '{}'. Valid days are: {}",
-            day,
-            valid_days.join(", ")
-        ));
+fn validate_block_durations(config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    for block in &config.blocks {
+        if let Some(delay) = &block.unblock_delay {
+            parse_duration(delay)
+                .map_err(|e| format!("Invalid unblock_delay for block '{}': {}", block.name, e))?;
+        }
+        if let Some(allowance) = &block.daily_allowance {
+            parse_duration(allowance).map_err(|e| {
+                format!("Invalid daily_allowance for block '{}': {}", block.name, e)
+            })?;
+        }
+        if let Some(action) = &block.application_action {
+            ApplicationAction::parse(action).map_err(|e| {
+                format!("Invalid application_action for block '{}': {}", block.name, e)
+            })?;
+        }
+        for pattern in block.applications.iter().flatten() {
+            apps::check_pattern(pattern).map_err(|e| {
+                format!("Invalid applications entry for block '{}': {}", block.name, e)
+            })?;
+        }
     }
 
     Ok(())
 }
 
-fn validate_schedule_times(config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+fn validate_exceptions(config: &Config) -> Result<(), String> {
+    let mut schedule_names = HashSet::new();
     for schedule in &config.schedule {
-        for day in &schedule.days {
-            validate_day(day)?;
+        if let Some(name) = &schedule.name
+            && !schedule_names.insert(name)
+        {
+            return Err(format!("Duplicate schedule name: '{name}'."));
         }
+    }
 
-        validate_time(&schedule.start).map_err(|e| {
-            format!(
-                "Invalid start time in schedule for block '{}': {}",
-                schedule.block, e
-            )
-        })?;
-        validate_time(&schedule.end).map_err(|e| {
-            format!(
-                "Invalid end time in schedule for block '{}': {}",
-                schedule.block, e
-            )
-        })?;
+    for (index, exception) in config.exceptions.iter().enumerate() {
+        let context = format!("Invalid exception #{}", index + 1);
+        exception
+            .date_range()
+            .map_err(|e| format!("{context}: {e}"))?;
+        if let Some(block) = exception
+            .blocks
+            .iter()
+            .flatten()
+            .find(|block| !config.blocks.iter().any(|b| b.name == **block))
+        {
+            return Err(format!("{context}: references non-existent block '{block}'."));
+        }
+        if let Some(name) = exception
+            .schedules
+            .iter()
+            .flatten()
+            .find(|name| !schedule_names.contains(name))
+        {
+            return Err(format!("{context}: references non-existent schedule '{name}'."));
+        }
     }
 
     Ok(())
 }
 
-fn validate_time(time_str: &str) -> Result<(i32, i32), String> {
-    if time_str.len() < 5 || !time_str.contains(':') {
-        return Err(format!("Invalid time format: {time_str}"));
-    }
+fn validate_sessions(config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let block_names: std::collections::HashSet<&String> =
+        config.blocks.iter().map(|b| &b.name).collect();
+    let mut session_names = std::collections::HashSet::new();
 
-    let parts: Vec<&str> = time_str.split(':').collect();
-    if parts.len() != 2 {
-        return Err(format!("Invalid time format: {time_str}"));
+    for session in &config.sessions {
+        if !session_names.insert(&session.name) {
+            return Err(format!("Duplicate session name: '{}'.", session.name).into());
+        }
+        if session.blocks.is_empty() {
+            return Err(format!("Session '{}' has no blocks.", session.name).into());
+        }
+        if let Some(missing) = session.blocks.iter().find(|b| !block_names.contains(b)) {
+            return Err(format!(
+                "Session '{}' references non-existent block: '{}'.",
+                session.name, missing
+            )
+            .into());
+        }
+        parse_duration(&session.duration)
+            .map_err(|e| format!("Invalid duration for session '{}': {}", session.name, e))?;
     }
 
-    let hour = parts[0]
-        .parse::<i32>()
-        .map_err(|_| format!("Invalid hour: {}", parts[0]))?;
-    let minute = parts[1]
-        .parse::<i32>()
-        .map_err(|_| format!("Invalid minute: {}", parts[1]))?;
+    Ok(())
+}
 
-    if !(0..=23).contains(&hour) {
-        return Err(format!("Hour out of range (0-23): {hour}"));
+fn apply_default_timezone(config: &mut Config) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(timezone) = &config.timezone {
+        parse_timezone(timezone)?;
+        for schedule in &mut config.schedule {
+            schedule.timezone.get_or_insert_with(|| timezone.clone());
+        }
     }
-    if !(0..=59).contains(&minute) {
-        return Err(format!("Minute out of range (0-59): {minute}"));
+    Ok(())
+}
+
+fn validate_schedule_times(config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    for (index, schedule) in config.schedule.iter().enumerate() {
+        let context = format!("schedule #{} for block '{}'", index + 1, schedule.block);
+        let cron_window = schedule
+            .cron_window()
+            .map_err(|e| format!("Invalid cron {context}: {e}"))?;
+        let calendar = schedule
+            .calendar()
+            .map_err(|e| format!("Invalid calendar {context}: {e}"))?;
+        schedule
+            .weekdays()
+            .map_err(|e| format!("Invalid days in {context}: {e}"))?;
+        schedule
+            .validity()
+            .map_err(|e| format!("Invalid dates in {context}: {e}"))?;
+        schedule
+            .zone()
+            .map_err(|e| format!("Invalid time zone in {context}: {e}"))?;
+
+        if cron_window.is_some() || calendar.is_some() {
+            continue;
+        }
+        parse_time_of_day(&schedule.start)
+            .map_err(|e| format!("Invalid start time in {context}: {e}"))?;
+        parse_time_of_day(&schedule.end)
+            .map_err(|e| format!("Invalid end time in {context}: {e}"))?;
     }
 
-    Ok((hour, minute))
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use chrono::TimeZone;
 
     #[test]
-    fn test_validate_day_valid_days() {
-        assert!(validate_day("Mon").is_ok());
-        assert!(validate_day("Tue").is_ok());
-        assert!(validate_day("Wed").is_ok());
-        assert!(validate_day("Thu").is_ok());
-        assert!(validate_day("Fri").is_ok());
-        assert!(validate_day("Sat").is_ok());
-        assert!(validate_day("Sun").is_ok());
+    fn test_schedule_days_are_expanded_once_in_order() {
+        let schedule = |days: &[&str]| Schedule {
+            block: "focus".to_string(),
+            days: days.iter().map(|d| d.to_string()).collect(),
+            start: "9:00".to_string(),
+            end: "17:00".to_string(),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            schedule(&["Sat", "mon-wed", "Tuesday"]).weekdays(),
+            Ok(vec![Weekday::Mon, Weekday::Tue, Weekday::Wed, Weekday::Sat])
+        );
+        assert!(schedule(&["Mon", "Funday"]).weekdays().is_err());
     }
 
     #[test]
-    fn test_validate_day_invalid_days() {
-        assert!(validate_day("monday").is_err());
-        assert!(validate_day("xyz").is_err());
-        assert!(validate_day("").is_err());
-        assert!(validate_day("123").is_err());
+    fn test_validate_schedule_times_uses_shared_parser() {
+        let schedule = |start: &str, end: &str| Config {
+            blocks: Vec::new(),
+            sessions: Vec::new(),
+            lists: BTreeMap::new(),
+            schedule: vec![Schedule {
+                block: "focus".to_string(),
+                days: vec!["Mon".to_string()],
+                start: start.to_string(),
+                end: end.to_string(),
+                ..Default::default()
+            }],
+            exceptions: Vec::new(),
+            timezone: None,
+        };
+
+        assert!(validate_schedule_times(&schedule("9:30", "5pm")).is_ok());
+
+        let error = validate_schedule_times(&schedule("09:00", "25:00")).unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .contains("end time in schedule #1 for block 'focus'")
+        );
+        assert!(error.to_string().contains("hour 25 is out of range"));
     }
 
     #[test]
-    fn test_validate_day_error_message() {
-        let result = validate_day("invalid");
-        assert!(result.is_err());
-        let error = result.unwrap_err();
-        assert!(error.contains("Invalid day: 'invalid'"));
-        assert!(error.contains("Valid days are:"));
+    fn test_unknown_keys_are_rejected() {
+        let contents = "[[blocks]]\nname = \"feeds\"\nblaclist = []\n";
+        let error = parse_config_str(contents, Path::new(".")).unwrap_err();
+        assert!(error.to_string().contains("unknown field `blaclist`"), "{error}");
+        for key in ["private = true", "alias = \"evenings\""] {
+            let contents = format!("[[blocks]]\nname = \"feeds\"\n{key}\n");
+            let error = parse_config_str(&contents, Path::new(".")).unwrap_err();
+            assert!(error.to_string().contains("unknown field"), "{error}");
+        }
+
+        assert_eq!(field_names::<PatternList>(), ["whitelist", "blacklist"]);
+        assert!(field_names::<Schedule>().contains(&"valid_until"));
+    }
+
+    #[test]
+    fn test_broad_application_patterns_are_rejected() {
+        for applications in [r#"[""]"#, r#"["steam", "/usr/"]"#] {
+            let contents = format!("[[blocks]]\nname = \"games\"\napplications = {applications}\n");
+            let error = parse_config_str(&contents, Path::new(".")).unwrap_err();
+            assert!(
+                error.to_string().starts_with("Invalid applications entry for block 'games'"),
+                "{error}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_overlaps_across_zones_use_the_offsets_at_now() {
+        let contents = r#"
+[[blocks]]
+name = "feeds"
+
+[[schedule]]
+block = "feeds"
+days = ["Mon"]
+start = "9:00"
+end = "10:00"
+timezone = "Europe/London"
+
+[[schedule]]
+block = "feeds"
+days = ["Mon"]
+start = "8:00"
+end = "9:00"
+timezone = "UTC"
+"#;
+        let config = parse_config_str(contents, Path::new(".")).unwrap();
+        let summer = Local.with_ymd_and_hms(2025, 7, 1, 12, 0, 0).unwrap();
+        let winter = Local.with_ymd_and_hms(2025, 12, 1, 12, 0, 0).unwrap();
+        assert_eq!(schedule_overlaps(&config.schedule, summer), [(0, 1)]);
+        assert!(schedule_overlaps(&config.schedule, winter).is_empty());
+    }
+
+    #[test]
+    fn test_cron_schedules_need_a_valid_expression_and_duration() {
+        let schedule = |cron: &str, duration: Option<&str>| Schedule {
+            block: "focus".to_string(),
+            cron: Some(cron.to_string()),
+            duration: duration.map(String::from),
+            ..Default::default()
+        };
+
+        let (cron, duration) = schedule("0 */2 * * *", Some("30m"))
+            .cron_window()
+            .unwrap()
+            .unwrap();
+        assert_eq!(cron.pattern.to_string(), "0 */2 * * *");
+        assert_eq!(duration, Duration::minutes(30));
+        assert!(schedule("0 9 * * MON#1", Some("3h")).cron_window().is_ok());
+
+        for (cron, duration, error) in [
+            ("0 25 * * *", Some("1h"), "Invalid cron expression"),
+            ("0 9 * *", Some("1h"), "Invalid cron expression"),
+            ("0 0 9 * * *", Some("1h"), "Invalid cron expression"),
+            ("0 9 * * *", None, "needs a `duration`"),
+            ("0 9 * * *", Some("2d"), "between 1 minute and 24 hours"),
+        ] {
+            let result = schedule(cron, duration).cron_window();
+            assert!(result.unwrap_err().contains(error), "{cron}");
+        }
+
+        let mixed = Schedule {
+            days: vec!["Mon".to_string()],
+            ..schedule("0 9 * * *", Some("1h"))
+        };
+        assert!(mixed.cron_window().unwrap_err().contains("use only one of"));
+    }
+
+    #[test]
+    fn test_calendar_schedules_resolve_their_path() {
+        let parse = |schedule: &str| {
+            parse_config_str(
+                &format!(
+                    "[[blocks]]\nname = \"focus\"\n\n[[schedule]]\nblock = \"focus\"\n{schedule}"
+                ),
+                Path::new("/etc/shire"),
+            )
+            .map_err(|e| e.to_string())
+        };
+
+        let config = parse("ics = \"calendars/work.ics\"\ntitle = \"Focus\"").unwrap();
+        let (path, title) = config.schedule[0].calendar().unwrap().unwrap();
+        assert_eq!(path, Path::new("/etc/shire/calendars/work.ics"));
+        assert_eq!(title, Some("Focus"));
+        assert_eq!(
+            config.schedule[0].describe(),
+            "events titled 'Focus' in /etc/shire/calendars/work.ics"
+        );
+
+        let error = parse("title = \"Focus\"\ncron = \"0 9 * * *\"\nduration = \"1h\"")
+            .unwrap_err();
+        assert!(error.contains("`title` only applies to `ics` schedules"), "{error}");
+        let error = parse("ics = \"work.ics\"\ndays = [\"Mon\"]").unwrap_err();
+        assert!(error.contains("use only one of"), "{error}");
+    }
+
+    #[test]
+    fn test_pattern_files_are_loaded_relative_to_config() {
+        let dir = std::env::temp_dir().join(format!("shire-config-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("social.txt"), "0.0.0.0 facebook.com\nreddit.com\n").unwrap();
+
+        let config = parse_config_str(
+            r#"
+            schedule = []
+
+            [[blocks]]
+            name = "social"
+            blacklist = ["reddit.com"]
+            blacklist_files = ["social.txt"]
+            "#,
+            &dir,
+        )
+        .unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(
+            config.blocks[0].blacklist.as_deref().unwrap(),
+            ["reddit.com", "facebook.com"]
+        );
+    }
+
+    #[test]
+    fn test_includes_are_flattened() {
+        let config = parse_config_str(
+            r#"
+            schedule = []
+
+            [lists.social]
+            blacklist = ["facebook.com", "instagram.com"]
+            whitelist = ["instagram.com/direct/inbox"]
+
+            [[blocks]]
+            name = "news"
+            blacklist = ["cnn.com", "facebook.com"]
+
+            [[blocks]]
+            name = "evening"
+            include = ["social", "news"]
+            blacklist = ["reddit.com"]
+            "#,
+            Path::new("."),
+        )
+        .unwrap();
+
+        let evening = config.blocks.iter().find(|b| b.name == "evening").unwrap();
+        assert_eq!(
+            evening.blacklist.as_deref().unwrap(),
+            ["reddit.com", "facebook.com", "instagram.com", "cnn.com"]
+        );
+        assert_eq!(
+            evening.whitelist.as_deref().unwrap(),
+            ["instagram.com/direct/inbox"]
+        );
+    }
+
+    #[test]
+    fn test_include_errors() {
+        let cycle = parse_config_str(
+            r#"
+            schedule = []
+
+            [[blocks]]
+            name = "a"
+            include = ["b"]
+
+            [[blocks]]
+            name = "b"
+            include = ["a"]
+            "#,
+            Path::new("."),
+        )
+        .unwrap_err();
+        assert!(
+            cycle
+                .to_string()
+                .contains("Block include cycle: a -> b -> a.")
+        );
+
+        let unknown = parse_config_str(
+            r#"
+            schedule = []
+
+            [[blocks]]
+            name = "a"
+            include = ["missing"]
+            "#,
+            Path::new("."),
+        )
+        .unwrap_err();
+        assert!(
+            unknown
+                .to_string()
+                .contains("unknown block or list: 'missing'")
+        );
+    }
+
+    #[test]
+    fn test_starter_config_is_valid() {
+        assert!(parse_config_str(STARTER_CONFIG, Path::new(".")).is_ok());
+        assert!(crate::validate::validate_str(STARTER_CONFIG, Path::new(".")).is_empty());
+    }
+
+    #[test]
+    fn test_write_starter_config_does_not_overwrite() {
+        let dir = std::env::temp_dir().join(format!("shire-init-{}", std::process::id()));
+        let path = dir.join("nested").join("shire.toml");
+
+        write_starter_config(&path, false).unwrap();
+        fs::write(&path, "# my config\n").unwrap();
+
+        let error = write_starter_config(&path, false).unwrap_err();
+        assert!(error.contains("already exists"));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "# my config\n");
+
+        write_starter_config(&path, true).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), STARTER_CONFIG);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_conf_d_fragments_are_merged_in_order() {
+        let dir = std::env::temp_dir().join(format!("shire-conf-d-{}", std::process::id()));
+        fs::create_dir_all(dir.join("conf.d/lists")).unwrap();
+        fs::write(
+            dir.join("shire.toml"),
+            "[[blocks]]\nname = \"work\"\nblacklist = [\"reddit.com\"]\n\n\
+             [lists.social]\nblacklist = [\"facebook.com\"]\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("conf.d/20-personal.toml"),
+            "[lists.social]\nblacklist = [\"x.com\"]\n\n\
+             [[schedule]]\nblock = \"team\"\ndays = [\"Mon\"]\nstart = \"9:00\"\nend = \"17:00\"\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("conf.d/10-team.toml"),
+            "[[blocks]]\nname = \"team\"\ninclude = [\"social\"]\n\
+             blacklist_files = [\"lists/news.txt\"]\n",
+        )
+        .unwrap();
+        fs::write(dir.join("conf.d/lists/news.txt"), "cnn.com\n").unwrap();
+        fs::write(dir.join("conf.d/notes.txt"), "not a fragment").unwrap();
+
+        let config = parse_config(Some(dir.join("shire.toml").display().to_string()));
+        fs::remove_dir_all(&dir).unwrap();
+        let config = config.unwrap();
+
+        let names: Vec<&str> = config.blocks.iter().map(|b| b.name.as_str()).collect();
+        assert_eq!(names, ["work", "team"]);
+        assert_eq!(
+            config.blocks[1].blacklist.as_deref().unwrap(),
+            ["cnn.com", "facebook.com", "x.com"]
+        );
+        assert_eq!(config.schedule[0].block, "team");
+    }
+
+    #[test]
+    fn test_encrypted_fragment_blocks_use_aliases() {
+        let dir = std::env::temp_dir().join(format!("shire-private-{}", std::process::id()));
+        fs::create_dir_all(dir.join("conf.d")).unwrap();
+        fs::write(dir.join("shire.toml"), "[[blocks]]\nname = \"work\"\n").unwrap();
+        private::create_keyfile(&private::keyfile_path(&dir)).unwrap();
+        let plaintext = r#"
+            [[blocks]]
+            name = "poker sites"
+            alias = "evenings"
+            blacklist = ["pokerstars.com"]
+
+            [[blocks]]
+            name = "ex"
+            include = ["poker sites"]
+
+            [[schedule]]
+            block = "poker sites"
+            days = ["Fri"]
+            start = "18:00"
+            end = "23:00"
+        "#;
+        let secret = private::load_secret(&dir).unwrap();
+        fs::write(
+            dir.join("conf.d/private.toml.enc"),
+            private::encrypt(plaintext, &secret).unwrap(),
+        )
+        .unwrap();
+
+        let config = parse_config(Some(dir.join("shire.toml").display().to_string()));
+        fs::remove_dir_all(&dir).unwrap();
+        let config = config.unwrap();
+
+        let names: Vec<(&str, bool)> = config
+            .blocks
+            .iter()
+            .map(|b| (b.name.as_str(), b.private))
+            .collect();
+        assert_eq!(names, [("work", false), ("evenings", true), ("private-2", true)]);
+        assert_eq!(config.blocks[1].alias.as_deref(), Some("evenings"));
+        assert_eq!(config.blocks[2].alias, None);
+        assert_eq!(config.schedule[0].block, "evenings");
+        assert_eq!(
+            config.blocks[2].blacklist.as_deref().unwrap(),
+            ["pokerstars.com"]
+        );
+    }
+
+    #[test]
+    fn test_encrypted_fragment_exceptions_use_aliases() {
+        let dir = std::env::temp_dir().join(format!("shire-private-exc-{}", std::process::id()));
+        fs::create_dir_all(dir.join("conf.d")).unwrap();
+        fs::write(dir.join("shire.toml"), "[[blocks]]\nname = \"work\"\n").unwrap();
+        private::create_keyfile(&private::keyfile_path(&dir)).unwrap();
+        let plaintext = r#"
+            [[blocks]]
+            name = "poker sites"
+            alias = "evenings"
+
+            [[exceptions]]
+            date = "2026-12-24"
+            blocks = ["poker sites", "work"]
+        "#;
+        let secret = private::load_secret(&dir).unwrap();
+        fs::write(
+            dir.join("conf.d/private.toml.enc"),
+            private::encrypt(plaintext, &secret).unwrap(),
+        )
+        .unwrap();
+
+        let config = parse_config(Some(dir.join("shire.toml").display().to_string()));
+        fs::remove_dir_all(&dir).unwrap();
+        let config = config.unwrap();
+
+        assert_eq!(config.exceptions[0].blocks.as_deref().unwrap(), ["evenings", "work"]);
     }
 }
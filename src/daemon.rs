@@ -1,38 +1,130 @@
+use crate::apps::{self, ApplicationAction, Enforcer, Rule, Signal, Suspended};
 use chrono::TimeZone;
-use crate::config;
+use crate::clock::Clock;
+use crate::config::{self, Config};
 use crate::state;
 use crate::state::*;
-use chrono::Datelike;
+use crate::store;
+use crate::timespec;
 use serde_json::Value;
 use shire_blocker::*;
 use std::collections::HashMap;
 use std::fs;
 use std::io;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
 use tokio::io::AsyncWriteExt;
 use tokio::net::{UnixListener, UnixStream};
 use tokio::sync::Mutex as AsyncMutex;
+use tokio::sync::Notify;
+use tokio::task::JoinSet;
 
 // Holds the full stream (not a split write-half) so that dropping one half
 // never triggers SHUT_RD and disconnects the bridge.
 type BridgeConn = Arc<AsyncMutex<Option<UnixStream>>>;
 
+/// Pending `shire block start --at` bookings, kept in the state directory.
+const ONE_OFFS_FILE: &str = "one_offs.json";
+/// Schedules added and paused with `shire schedule`, kept in the state directory.
+const SCHEDULE_OVERLAY_FILE: &str = "schedule_overlay.json";
+/// Today's use of daily allowances, kept in the state directory.
+const ALLOWANCE_USAGE_FILE: &str = "allowance_usage.json";
+/// Processes suspended for blocks, kept in the state directory so they're
+/// resumed even if the daemon dies.
+const SUSPENDED_FILE: &str = "suspended.json";
+/// How often timers, one-offs and pomodoro phases are checked.
+const TIMER_INTERVAL: chrono::Duration = chrono::Duration::seconds(1);
+/// How often calendar files are checked for changes.
+const CALENDAR_POLL_INTERVAL: chrono::Duration = chrono::Duration::seconds(5);
+/// How often running processes are checked against active blocks.
+const APPLICATION_SCAN_INTERVAL: chrono::Duration = chrono::Duration::seconds(2);
+const PROC_DIR: &str = "/proc";
+
+/// Where a daemon listens and keeps its state, and the clock it runs on.
+pub struct DaemonEnv {
+    pub clock: Clock,
+    pub cli_socket: PathBuf,
+    pub bridge_socket: PathBuf,
+    pub state_dir: PathBuf,
+}
+
 pub async fn start_daemon(config_path: Option<String>) {
-    let config = config::parse_config(config_path).unwrap();
-    let app_state: Arc<Mutex<ApplicationState>> = initialize_application_state(config.clone());
+    let config = config::parse_config(config_path).unwrap_or_else(|e| {
+        eprintln!("Invalid configuration: {e}");
+        eprintln!("Run `shire config validate` to list every problem with its position.");
+        std::process::exit(1);
+    });
+    let state_dir = store::state_dir().unwrap_or_else(|e| {
+        eprintln!("Failed to locate the state directory: {e}");
+        std::process::exit(1);
+    });
+
+    run_daemon(
+        config,
+        DaemonEnv {
+            clock: Clock::System,
+            cli_socket: PathBuf::from(CLI_SOCKET_PATH),
+            bridge_socket: PathBuf::from(BRIDGE_SOCKET_PATH),
+            state_dir,
+        },
+        shutdown_signal(),
+    )
+    .await;
+}
+
+/// Completes on SIGTERM or SIGINT.
+async fn shutdown_signal() {
+    let mut terminate =
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()).unwrap();
+    tokio::select! {
+        _ = terminate.recv() => {}
+        _ = tokio::signal::ctrl_c() => {}
+    }
+}
+
+/// Serves the CLI and the bridge, and runs the schedule, timers and
+/// calendar reloads, all on `env`'s clock. Returns once `shutdown`
+/// completes, after stopping its tasks and resuming any suspended
+/// applications.
+pub async fn run_daemon(config: Config, env: DaemonEnv, shutdown: impl Future<Output = ()>) {
+    let env = Arc::new(env);
+    let schedule_overlay = store::load(&env.state_dir.join(SCHEDULE_OVERLAY_FILE))
+        .unwrap_or_else(|e| {
+            eprintln!("Failed to load schedule changes: {e}");
+            ScheduleOverlay::default()
+        });
+    let app_state: Arc<Mutex<ApplicationState>> =
+        initialize_application_state(config, schedule_overlay, env.clock.now());
+    match store::load(&env.state_dir.join(ONE_OFFS_FILE)) {
+        Ok(one_offs) => app_state.lock().unwrap().one_offs = one_offs,
+        Err(e) => eprintln!("Failed to load pending block starts: {e}"),
+    }
+    match store::load(&env.state_dir.join(ALLOWANCE_USAGE_FILE)) {
+        Ok(usage) => {
+            restore_allowance_usage(&mut app_state.lock().unwrap(), &usage, env.clock.now())
+        }
+        Err(e) => eprintln!("Failed to load allowance usage: {e}"),
+    }
+    // Left over from a daemon that didn't get to resume them
+    resume_suspended_applications(&app_state, &env);
 
-    let _ = fs::remove_file(BRIDGE_SOCKET_PATH);
-    let _ = fs::remove_file(CLI_SOCKET_PATH);
+    let _ = fs::remove_file(&env.bridge_socket);
+    let _ = fs::remove_file(&env.cli_socket);
 
-    let bridge_listener = UnixListener::bind(BRIDGE_SOCKET_PATH).unwrap();
-    let cli_listener = UnixListener::bind(CLI_SOCKET_PATH).unwrap();
+    let bridge_listener = UnixListener::bind(&env.bridge_socket).unwrap();
+    let cli_listener = UnixListener::bind(&env.cli_socket).unwrap();
 
     let bridge_conn: BridgeConn = Arc::new(AsyncMutex::new(None));
+    // Wakes the schedule task when schedules are added or removed
+    let schedule_changed = Arc::new(Notify::new());
+    // Every task the daemon starts, so shutting down stops all of them
+    let mut tasks = JoinSet::new();
 
     // Bridge listener task
     let bridge_conn_for_accept = Arc::clone(&bridge_conn);
     let bridge_app_state = Arc::clone(&app_state);
-    tokio::spawn(async move {
+    tasks.spawn(async move {
         loop {
             match bridge_listener.accept().await {
                 Ok((mut stream, _)) => {
@@ -62,90 +154,260 @@ pub async fn start_daemon(config_path: Option<String>) {
     // Schedule task
     let bridge_conn_for_schedule = Arc::clone(&bridge_conn);
     let schedule_app_state = Arc::clone(&app_state);
-    tokio::spawn(async move {
-        let current_day = state::OrderableWeekday(chrono::Local::now().weekday());
-        let current_time = chrono::Local::now().time();
-
-        let schedule = schedule_app_state.lock().unwrap().schedule.clone();
-
-        let mut next_event = schedule
-            .iter()
-            .find(|ev| ev.day > current_day || (ev.day == current_day && ev.time > current_time))
-            .or_else(|| schedule.first())
-            .cloned();
+    let schedule_changed_for_task = Arc::clone(&schedule_changed);
+    let schedule_clock = env.clock.clone();
+    tasks.spawn(async move {
+        let mut last_fired = schedule_clock.now();
 
         loop {
-            let Some(event) = &next_event else { break };
+            let schedule = schedule_app_state.lock().unwrap().schedule.clone();
+            let next = state::next_scheduled_events(&schedule, last_fired);
+            let wake_at = match &next {
+                Some((at, _)) => {
+                    println!("Sleeping until {at}");
+                    *at
+                }
+                // Nothing within the horizon, e.g. every schedule has expired
+                None => schedule_clock.now() + chrono::Duration::hours(1),
+            };
 
-            let now = chrono::Local::now();
-            let current_weekday = now.weekday();
-            let current_time_now = now.time();
+            tokio::select! {
+                _ = schedule_clock.sleep_until(wake_at) => {}
+                _ = schedule_changed_for_task.notified() => {
+                    // Changes apply from now on; open windows were handled when they were made
+                    last_fired = last_fired.max(schedule_clock.now());
+                    continue;
+                }
+            }
 
-            let target_date = if event.day.0.num_days_from_monday() > current_weekday.num_days_from_monday()
-                || (event.day.0 == current_weekday && event.time > current_time_now)
-            {
-                let days_until = event.day.0.num_days_from_monday() as i64
-                    - current_weekday.num_days_from_monday() as i64;
-                now.date_naive() + chrono::Duration::days(days_until)
-            } else {
-                let days_until = 7 - current_weekday.num_days_from_monday() as i64
-                    + event.day.0.num_days_from_monday() as i64;
-                now.date_naive() + chrono::Duration::days(days_until)
+            let Some((at, events)) = next else {
+                last_fired = last_fired.max(schedule_clock.now());
+                continue;
             };
+            last_fired = at;
 
-            let target_datetime = target_date.and_time(event.time);
-            let target_local = chrono::Local.from_local_datetime(&target_datetime).unwrap();
-
-            match (target_local - now).to_std() {
-                Ok(duration) => {
-                    println!("Sleeping until {:?}", duration);
-                    tokio::time::sleep(duration).await;
+            let state_bytes = {
+                let mut guard = schedule_app_state.lock().unwrap();
+                // Applied as of when they were due, so catching up after a
+                // long sleep replays each window in order
+                for event in &events {
+                    apply_scheduled_event(&mut guard, event, at);
+                }
+                serialize_state(&guard)
+            };
 
-                    let state_bytes = {
-                        let mut guard = schedule_app_state.lock().unwrap();
-                        match event.action {
-                            state::ScheduleAction::StartBlock => {
-                                update_block(&mut guard, &event.block, BlockState::Blocked);
-                            }
-                            state::ScheduleAction::EndBlock => {
-                                update_block(&mut guard, &event.block, BlockState::Unblocked);
-                            }
-                        }
-                        serialize_state(&guard)
-                    };
+            send_to_bridge(&bridge_conn_for_schedule, &state_bytes).await;
+        }
+    });
 
-                    send_to_bridge(&bridge_conn_for_schedule, &state_bytes).await;
+    // Timer task
+    let bridge_conn_for_timers = Arc::clone(&bridge_conn);
+    let timer_app_state = Arc::clone(&app_state);
+    let timer_env = Arc::clone(&env);
+    tasks.spawn(async move {
+        loop {
+            let clock = &timer_env.clock;
+            clock.sleep_until(clock.now() + TIMER_INTERVAL).await;
 
-                    let current_index = schedule.iter().position(|e| e == event).unwrap_or(0);
-                    let next_index = (current_index + 1) % schedule.len();
-                    next_event = Some(schedule[next_index].clone());
+            let state_bytes = {
+                let mut guard = timer_app_state.lock().unwrap();
+                let now = clock.now();
+                let fired = fire_one_offs(&mut guard, now);
+                if !fired.is_empty() {
+                    save_one_offs(&guard, &timer_env.state_dir);
                 }
-                Err(_) => {
-                    tokio::time::sleep(std::time::Duration::from_secs(60)).await;
+                if !expire_timers(&mut guard, now) && fired.is_empty() {
+                    continue;
                 }
+                serialize_state(&guard)
+            };
+
+            send_to_bridge(&bridge_conn_for_timers, &state_bytes).await;
+        }
+    });
+
+    // Calendar task: re-reads `ics` schedules when their file changes, and
+    // each day so the expanded events keep reaching the full horizon
+    let bridge_conn_for_calendars = Arc::clone(&bridge_conn);
+    let calendar_app_state = Arc::clone(&app_state);
+    let schedule_changed_for_calendars = Arc::clone(&schedule_changed);
+    let calendar_clock = env.clock.clone();
+    tasks.spawn(async move {
+        let files = calendar_files(&calendar_app_state.lock().unwrap());
+        if files.is_empty() {
+            return;
+        }
+        let mut seen: Vec<_> = files.iter().map(|file| file_version(file)).collect();
+        let mut loaded_on = calendar_clock.now().date_naive();
+        loop {
+            calendar_clock.sleep_until(calendar_clock.now() + CALENDAR_POLL_INTERVAL).await;
+
+            let now = calendar_clock.now();
+            let versions: Vec<_> = files.iter().map(|file| file_version(file)).collect();
+            if versions == seen && now.date_naive() == loaded_on {
+                continue;
+            }
+            if versions != seen {
+                println!("Calendar changed, reloading");
+            }
+            seen = versions;
+            loaded_on = now.date_naive();
+
+            let state_bytes = {
+                let mut guard = calendar_app_state.lock().unwrap();
+                let changed = reload_calendars(&mut guard, now);
+                changed.then(|| serialize_state(&guard))
+            };
+            schedule_changed_for_calendars.notify_one();
+            if let Some(state_bytes) = state_bytes {
+                send_to_bridge(&bridge_conn_for_calendars, &state_bytes).await;
             }
         }
     });
 
+    // Application task: stops the processes of applications that an active
+    // block lists
+    let application_app_state = Arc::clone(&app_state);
+    let application_clock = env.clock.clone();
+    let application_env = Arc::clone(&env);
+    tasks.spawn(async move {
+        let uses_applications = application_app_state
+            .lock()
+            .unwrap()
+            .blocks
+            .values()
+            .any(|block| !block.applications.is_empty());
+        if !uses_applications {
+            return;
+        }
+        if !Path::new(PROC_DIR).is_dir() {
+            eprintln!("Application blocking needs {PROC_DIR}, which this system doesn't have");
+            return;
+        }
+
+        let mut enforcer = Enforcer::default();
+        loop {
+            let blocks: Vec<(String, Vec<String>, ApplicationAction)> = application_app_state
+                .lock()
+                .unwrap()
+                .blocks
+                .iter()
+                .filter(|(_, block)| {
+                    block.block_state == BlockState::Blocked && !block.applications.is_empty()
+                })
+                .map(|(name, block)| {
+                    (name.clone(), block.applications.clone(), block.application_action)
+                })
+                .collect();
+            let rules: Vec<Rule> = blocks
+                .iter()
+                .map(|(block, applications, action)| Rule {
+                    block,
+                    applications,
+                    action: *action,
+                })
+                .collect();
+
+            let processes = apps::scan_processes(Path::new(PROC_DIR), apps::current_uid());
+            let actions = enforcer.plan(&processes, &rules, application_clock.now());
+            // Saved before signalling, so a stopped process is always on file
+            if actions
+                .iter()
+                .any(|action| matches!(action.signal, Signal::Stop | Signal::Continue))
+            {
+                save_suspended(&enforcer.suspended(), &application_env.state_dir);
+            }
+            send_application_actions(actions, &application_app_state);
+
+            application_clock
+                .sleep_until(application_clock.now() + APPLICATION_SCAN_INTERVAL)
+                .await;
+        }
+    });
+
     // CLI listener loop
+    let mut shutdown = std::pin::pin!(shutdown);
     loop {
-        match cli_listener.accept().await {
+        let accepted = tokio::select! {
+            accepted = cli_listener.accept() => accepted,
+            () = &mut shutdown => break,
+        };
+        // Let go of finished requests
+        while tasks.try_join_next().is_some() {}
+        match accepted {
             Ok((mut stream, _)) => {
                 let cli_app_state = Arc::clone(&app_state);
                 let bridge_conn_clone = Arc::clone(&bridge_conn);
-                tokio::spawn(async move {
-                    handle_cli_request(&mut stream, cli_app_state, bridge_conn_clone).await;
+                let schedule_changed_clone = Arc::clone(&schedule_changed);
+                let cli_env = Arc::clone(&env);
+                tasks.spawn(async move {
+                    handle_cli_request(
+                        &mut stream,
+                        cli_app_state,
+                        bridge_conn_clone,
+                        schedule_changed_clone,
+                        &cli_env,
+                    )
+                    .await;
                 });
             }
             Err(e) => eprintln!("CLI connection failed: {e}"),
         }
     }
+
+    println!("Shutting down");
+    // The application task stops between scans, so nothing it suspended is
+    // missing from the file
+    tasks.shutdown().await;
+    resume_suspended_applications(&app_state, &env);
+}
+
+/// Sends the signals for blocks' applications, logging each one with its
+/// block.
+fn send_application_actions(actions: Vec<apps::Action>, app_state: &Mutex<ApplicationState>) {
+    for action in actions {
+        let description = format!(
+            "Block '{}': {} {} (pid {})",
+            action.block,
+            action.signal.describe(),
+            action.name,
+            action.pid
+        );
+        match action.send() {
+            Ok(()) => {
+                println!("{description}");
+                let mut guard = app_state.lock().unwrap();
+                if let Some(block) = guard.blocks.get_mut(&action.block) {
+                    block.log_application_action(action);
+                }
+            }
+            Err(e) => eprintln!("{description} failed: {e}"),
+        }
+    }
+}
+
+/// Resumes the processes in the suspended file that are still running, and
+/// empties it.
+fn resume_suspended_applications(app_state: &Mutex<ApplicationState>, env: &DaemonEnv) {
+    let path = env.state_dir.join(SUSPENDED_FILE);
+    let saved: Vec<Suspended> = store::load(&path).unwrap_or_else(|e| {
+        eprintln!("Failed to load suspended applications: {e}");
+        Vec::new()
+    });
+    if saved.is_empty() {
+        return;
+    }
+    let processes = apps::scan_processes(Path::new(PROC_DIR), apps::current_uid());
+    send_application_actions(apps::resume_saved(&saved, &processes, env.clock.now()), app_state);
+    save_suspended(&[], &env.state_dir);
 }
 
 async fn handle_cli_request(
     cli_stream: &mut UnixStream,
     app_state: Arc<Mutex<ApplicationState>>,
     bridge_conn: BridgeConn,
+    schedule_changed: Arc<Notify>,
+    env: &DaemonEnv,
 ) {
     let response = match recv_length_prefixed_message_async(cli_stream).await {
         Ok(r) => r,
@@ -165,12 +427,27 @@ async fn handle_cli_request(
         Some("list_blocks") => {
             let message = {
                 let guard = app_state.lock().unwrap();
-                let string_map: HashMap<String, BlockState> = guard
+                let now = env.clock.now();
+                let block_map: HashMap<&String, Value> = guard
                     .blocks
                     .iter()
-                    .map(|(name, block)| (name.clone(), block.block_state))
+                    .map(|(name, block)| {
+                        let remaining = |at: Option<chrono::DateTime<chrono::Local>>| {
+                            at.filter(|at| *at > now).map(|at| (at - now).num_seconds())
+                        };
+                        let info = serde_json::json!({
+                            "state": block.block_state,
+                            "active_because": block.active_because,
+                            "unblock_in": remaining(block.unblock_at),
+                            "locked_for": remaining(block.locked_until),
+                            "pending_unblock_in": remaining(block.pending_unblock_at),
+                            "allowance_left": block.allowance_left().map(|left| left.num_seconds()),
+                            "applications": block.application_log,
+                        });
+                        (name, info)
+                    })
                     .collect();
-                serde_json::json!({ "blocks": string_map }).to_string().into_bytes()
+                serde_json::json!({ "blocks": block_map }).to_string().into_bytes()
             };
 
             if let Err(e) = send_length_prefixed_message_async(cli_stream, &message).await {
@@ -178,43 +455,410 @@ async fn handle_cli_request(
             }
         }
 
-        // TODO: Update this block to handle locks?
         Some("start_block") => {
             if let Some(block_name) = v["name"].as_str().map(String::from) {
-                let state_bytes = {
+                let until = parse_timestamp(&v["until"]);
+                let lock_until = parse_timestamp(&v["lock_until"]);
+                let result = {
                     let mut guard = app_state.lock().unwrap();
-                    update_block(&mut guard, &block_name, BlockState::Blocked);
-                    serialize_state(&guard)
+                    start_block(&mut guard, &block_name, until, lock_until, env.clock.now())
+                        .map(|_| serialize_state(&guard))
                 };
 
-                send_to_bridge(&bridge_conn, &state_bytes).await;
-
-                let ack = serde_json::json!({ "status": "started", "block": block_name })
-                    .to_string()
-                    .into_bytes();
-                if let Err(e) = send_length_prefixed_message_async(cli_stream, &ack).await {
-                    eprintln!("Failed to send CLI ack: {e}");
+                if let Ok(state_bytes) = &result {
+                    send_to_bridge(&bridge_conn, state_bytes).await;
                 }
+                let ack = result
+                    .map(|_| serde_json::json!({ "status": "started", "block": block_name }));
+                respond_to_cli(cli_stream, ack).await;
             }
         }
 
-        // TODO: Update this block to error and respond to CLI if a block is locked
         Some("stop_block") => {
             if let Some(block_name) = v["name"].as_str().map(String::from) {
-                let state_bytes = {
+                let result = {
+                    let mut guard = app_state.lock().unwrap();
+                    request_stop(&mut guard, &block_name, env.clock.now())
+                        .map(|outcome| (outcome, serialize_state(&guard)))
+                };
+
+                let ack = match result {
+                    Ok((StopOutcome::Stopped, state_bytes)) => {
+                        send_to_bridge(&bridge_conn, &state_bytes).await;
+                        Ok(serde_json::json!({ "status": "stopped", "block": block_name }))
+                    }
+                    Ok((StopOutcome::Pending(at), _)) => Ok(serde_json::json!({
+                        "status": "pending",
+                        "block": block_name,
+                        "message": format!(
+                            "Block '{block_name}' will be unblocked at {}. Run `shire block cancel \"{block_name}\"` to keep it active.",
+                            at.format("%H:%M:%S")
+                        ),
+                    })),
+                    Err(e) => Err(e),
+                };
+                respond_to_cli(cli_stream, ack).await;
+            }
+        }
+
+        Some("schedule_block") => {
+            if let Some(block_name) = v["name"].as_str().map(String::from) {
+                let result = match parse_timestamp(&v["at"]) {
+                    Some(at) => {
+                        let mut guard = app_state.lock().unwrap();
+                        add_one_off(
+                            &mut guard,
+                            &block_name,
+                            at,
+                            parse_timestamp(&v["until"]),
+                            parse_timestamp(&v["lock_until"]),
+                            env.clock.now(),
+                        )
+                        .inspect(|_| save_one_offs(&guard, &env.state_dir))
+                    }
+                    None => Err("Missing start time".to_string()),
+                };
+
+                let ack = result.map(|one_off| {
+                    serde_json::json!({
+                        "status": "scheduled",
+                        "message": format!(
+                            "Block '{block_name}' will start at {} (id {}).",
+                            one_off.at.format("%Y-%m-%d %H:%M"),
+                            one_off.id
+                        ),
+                    })
+                });
+                respond_to_cli(cli_stream, ack).await;
+            }
+        }
+
+        Some("list_one_offs") => {
+            let one_offs = app_state.lock().unwrap().one_offs.clone();
+            respond_to_cli(cli_stream, Ok(serde_json::json!({ "one_offs": one_offs }))).await;
+        }
+
+        Some("cancel_one_off") => {
+            let result = match v["id"].as_u64() {
+                Some(id) => {
+                    let mut guard = app_state.lock().unwrap();
+                    cancel_one_off(&mut guard, id as u32)
+                        .inspect(|_| save_one_offs(&guard, &env.state_dir))
+                }
+                None => Err("Missing id".to_string()),
+            };
+
+            let ack = result.map(|one_off| {
+                serde_json::json!({
+                    "status": "cancelled",
+                    "message": format!(
+                        "Cancelled the start of '{}' at {}.",
+                        one_off.block,
+                        one_off.at.format("%Y-%m-%d %H:%M")
+                    ),
+                })
+            });
+            respond_to_cli(cli_stream, ack).await;
+        }
+
+        Some("list_schedule") => {
+            let schedule = {
+                let guard = app_state.lock().unwrap();
+                let entry = |schedule: &config::Schedule, id: Option<u32>| {
+                    let zone = schedule.zone().ok().flatten();
+                    let now = env.clock.now();
+                    let local =
+                        |time: &str| zone.and_then(|zone| local_equivalent(time, zone, now));
+                    serde_json::json!({
+                        "id": id,
+                        "name": schedule.name,
+                        "block": schedule.block,
+                        "days": schedule.days,
+                        "start": schedule.start,
+                        "end": schedule.end,
+                        "cron": schedule.cron,
+                        "duration": schedule.duration,
+                        "ics": schedule.ics,
+                        "title": schedule.title,
+                        "timezone": schedule.timezone,
+                        "local_start": local(&schedule.start),
+                        "local_end": local(&schedule.end),
+                    })
+                };
+                let entries: Vec<Value> = guard
+                    .config_schedules
+                    .iter()
+                    .map(|schedule| entry(schedule, None))
+                    .chain(
+                        guard
+                            .schedule_overlay
+                            .added
+                            .iter()
+                            .map(|added| entry(&added.schedule, Some(added.id))),
+                    )
+                    .collect();
+                serde_json::json!({
+                    "schedules": entries,
+                    "pauses": guard.schedule_overlay.pauses,
+                })
+            };
+            respond_to_cli(cli_stream, Ok(schedule)).await;
+        }
+
+        Some("add_schedule") => {
+            let result = match serde_json::from_value::<config::Schedule>(v["schedule"].clone()) {
+                Ok(schedule) => {
+                    let mut guard = app_state.lock().unwrap();
+                    add_schedule(&mut guard, schedule, env.clock.now()).map(|id| {
+                        save_schedule_overlay(&guard, &env.state_dir);
+                        (id, serialize_state(&guard))
+                    })
+                }
+                Err(e) => Err(format!("Invalid schedule: {e}")),
+            };
+
+            let ack = match result {
+                Ok((id, state_bytes)) => {
+                    schedule_changed.notify_one();
+                    send_to_bridge(&bridge_conn, &state_bytes).await;
+                    Ok(serde_json::json!({
+                        "status": "added",
+                        "message": format!("Added schedule {id}."),
+                    }))
+                }
+                Err(e) => Err(e),
+            };
+            respond_to_cli(cli_stream, ack).await;
+        }
+
+        Some("remove_schedule") => {
+            let result = match v["id"].as_u64() {
+                Some(id) => {
+                    let mut guard = app_state.lock().unwrap();
+                    remove_schedule(&mut guard, id as u32, env.clock.now()).map(|removed| {
+                        save_schedule_overlay(&guard, &env.state_dir);
+                        (removed, serialize_state(&guard))
+                    })
+                }
+                None => Err("Missing id".to_string()),
+            };
+
+            let ack = match result {
+                Ok((removed, state_bytes)) => {
+                    schedule_changed.notify_one();
+                    send_to_bridge(&bridge_conn, &state_bytes).await;
+                    Ok(serde_json::json!({
+                        "status": "removed",
+                        "message": format!(
+                            "Removed schedule {} for block '{}'.",
+                            removed.id, removed.schedule.block
+                        ),
+                    }))
+                }
+                Err(e) => Err(e),
+            };
+            respond_to_cli(cli_stream, ack).await;
+        }
+
+        Some("pause_schedule") => {
+            if let Some(block_name) = v["name"].as_str().map(String::from) {
+                let result = match parse_timestamp(&v["until"]) {
+                    Some(until) => {
+                        let mut guard = app_state.lock().unwrap();
+                        pause_schedule(&mut guard, &block_name, until, env.clock.now()).map(
+                            |outcome| {
+                                save_schedule_overlay(&guard, &env.state_dir);
+                                (until, outcome, serialize_state(&guard))
+                            },
+                        )
+                    }
+                    None => Err("Missing end of the pause".to_string()),
+                };
+
+                let ack = match result {
+                    Ok((until, outcome, state_bytes)) => {
+                        send_to_bridge(&bridge_conn, &state_bytes).await;
+                        let mut message = format!(
+                            "Schedule for '{block_name}' paused until {}.",
+                            until.format("%Y-%m-%d %H:%M")
+                        );
+                        if let Some(StopOutcome::Pending(at)) = outcome {
+                            message.push_str(&format!(
+                                " The block unblocks at {} after its delay.",
+                                at.format("%H:%M:%S")
+                            ));
+                        }
+                        Ok(serde_json::json!({ "status": "paused", "message": message }))
+                    }
+                    Err(e) => Err(e),
+                };
+                respond_to_cli(cli_stream, ack).await;
+            }
+        }
+
+        Some("cancel_unblock") => {
+            if let Some(block_name) = v["name"].as_str().map(String::from) {
+                let result = {
                     let mut guard = app_state.lock().unwrap();
-                    update_block(&mut guard, &block_name, BlockState::Unblocked);
-                    serialize_state(&guard)
+                    cancel_pending_unblock(&mut guard, &block_name)
                 };
 
-                send_to_bridge(&bridge_conn, &state_bytes).await;
+                let ack = result
+                    .map(|_| serde_json::json!({ "status": "cancelled", "block": block_name }));
+                respond_to_cli(cli_stream, ack).await;
+            }
+        }
+
+        Some("lock_block") => {
+            if let Some(block_name) = v["name"].as_str().map(String::from) {
+                let result = match parse_timestamp(&v["lock_until"]) {
+                    Some(lock_until) => {
+                        let mut guard = app_state.lock().unwrap();
+                        lock_block(&mut guard, &block_name, lock_until, env.clock.now())
+                            .map(|_| serialize_state(&guard))
+                    }
+                    None => Err("Missing lock time".to_string()),
+                };
 
-                let ack = serde_json::json!({ "status": "stopped", "block": block_name })
-                    .to_string()
-                    .into_bytes();
-                if let Err(e) = send_length_prefixed_message_async(cli_stream, &ack).await {
-                    eprintln!("Failed to send CLI ack: {e}");
+                if let Ok(state_bytes) = &result {
+                    send_to_bridge(&bridge_conn, state_bytes).await;
                 }
+                let ack = result
+                    .map(|_| serde_json::json!({ "status": "locked", "block": block_name }));
+                respond_to_cli(cli_stream, ack).await;
+            }
+        }
+
+        Some("start_pomodoro") => {
+            let blocks: Vec<String> = v["blocks"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|name| name.as_str().map(String::from))
+                .collect();
+            let work = chrono::Duration::seconds(v["work_secs"].as_i64().unwrap_or(0));
+            let break_duration = chrono::Duration::seconds(v["break_secs"].as_i64().unwrap_or(0));
+            let cycles = v["cycles"].as_u64().unwrap_or(0) as u32;
+
+            let result = {
+                let mut guard = app_state.lock().unwrap();
+                start_pomodoro(&mut guard, blocks, work, break_duration, cycles, env.clock.now())
+                    .map(|_| serialize_state(&guard))
+            };
+
+            if let Ok(state_bytes) = &result {
+                send_to_bridge(&bridge_conn, state_bytes).await;
+            }
+            respond_to_cli(cli_stream, result.map(|_| serde_json::json!({ "status": "started" })))
+                .await;
+        }
+
+        Some("stop_pomodoro") => {
+            let result = {
+                let mut guard = app_state.lock().unwrap();
+                stop_pomodoro(&mut guard, env.clock.now()).map(|_| serialize_state(&guard))
+            };
+
+            if let Ok(state_bytes) = &result {
+                send_to_bridge(&bridge_conn, state_bytes).await;
+            }
+            respond_to_cli(cli_stream, result.map(|_| serde_json::json!({ "status": "stopped" })))
+                .await;
+        }
+
+        Some("pomodoro_status") => {
+            let status = {
+                let guard = app_state.lock().unwrap();
+                match &guard.pomodoro {
+                    Some(pomodoro) => serde_json::json!({
+                        "status": "running",
+                        "phase": pomodoro.phase,
+                        "cycle": pomodoro.cycle,
+                        "cycles": pomodoro.cycles,
+                        "remaining": (pomodoro.phase_ends_at - env.clock.now())
+                            .num_seconds()
+                            .max(0),
+                        "blocks": pomodoro.blocks,
+                    }),
+                    None => serde_json::json!({ "status": "idle" }),
+                }
+            };
+            respond_to_cli(cli_stream, Ok(status)).await;
+        }
+
+        Some("start_session") => {
+            if let Some(session_name) = v["name"].as_str().map(String::from) {
+                let until = parse_timestamp(&v["until"]);
+                let result = {
+                    let mut guard = app_state.lock().unwrap();
+                    start_session(&mut guard, &session_name, until, env.clock.now())
+                        .map(|session| (session, serialize_state(&guard)))
+                };
+
+                let ack = match result {
+                    Ok((session, state_bytes)) => {
+                        send_to_bridge(&bridge_conn, &state_bytes).await;
+                        Ok(serde_json::json!({
+                            "status": "started",
+                            "message": format!(
+                                "Session '{session_name}' started until {}{}.",
+                                session.ends_at.format("%H:%M"),
+                                if session.locked { " (locked)" } else { "" }
+                            ),
+                        }))
+                    }
+                    Err(e) => Err(e),
+                };
+                respond_to_cli(cli_stream, ack).await;
+            }
+        }
+
+        Some("stop_session") => {
+            let result = {
+                let mut guard = app_state.lock().unwrap();
+                stop_session(&mut guard, env.clock.now()).map(|_| serialize_state(&guard))
+            };
+
+            if let Ok(state_bytes) = &result {
+                send_to_bridge(&bridge_conn, state_bytes).await;
+            }
+            respond_to_cli(cli_stream, result.map(|_| serde_json::json!({ "status": "stopped" })))
+                .await;
+        }
+
+        Some("session_status") => {
+            let status = {
+                let guard = app_state.lock().unwrap();
+                match &guard.active_session {
+                    Some(session) => serde_json::json!({
+                        "status": "running",
+                        "name": session.name,
+                        "blocks": session.blocks,
+                        "locked": session.locked,
+                        "remaining": (session.ends_at - env.clock.now()).num_seconds().max(0),
+                    }),
+                    None => serde_json::json!({ "status": "idle" }),
+                }
+            };
+            respond_to_cli(cli_stream, Ok(status)).await;
+        }
+
+        // Sent by the bridge with the browser's focused tab
+        Some("report_activity") => {
+            if let Some(url) = v["url"].as_str() {
+                // Cap each report so a misbehaving client can't drain an allowance at once
+                let seconds = v["seconds"].as_i64().unwrap_or(0).clamp(0, 60);
+                let state_bytes = {
+                    let mut guard = app_state.lock().unwrap();
+                    let changed = record_activity(&mut guard, url, seconds, env.clock.now());
+                    save_allowance_usage(&guard, &env.state_dir);
+                    changed.then(|| serialize_state(&guard))
+                };
+
+                if let Some(state_bytes) = state_bytes {
+                    send_to_bridge(&bridge_conn, &state_bytes).await;
+                }
+                respond_to_cli(cli_stream, Ok(serde_json::json!({ "status": "recorded" }))).await;
             }
         }
 
@@ -222,6 +866,19 @@ async fn handle_cli_request(
     }
 }
 
+/// Acknowledges the CLI, or reports the error back if the request was
+/// rejected.
+async fn respond_to_cli(cli_stream: &mut UnixStream, result: Result<Value, String>) {
+    let response = result
+        .unwrap_or_else(|e| serde_json::json!({ "status": "error", "message": e }));
+
+    if let Err(e) =
+        send_length_prefixed_message_async(cli_stream, response.to_string().as_bytes()).await
+    {
+        eprintln!("Failed to send CLI ack: {e}");
+    }
+}
+
 async fn send_to_bridge(bridge_conn: &BridgeConn, bytes: &[u8]) {
     let mut guard = bridge_conn.lock().await;
     if let Some(ref mut stream) = *guard {
@@ -249,6 +906,65 @@ async fn send_to_bridge(bridge_conn: &BridgeConn, bytes: &[u8]) {
     }
 }
 
+fn save_one_offs(app_state: &ApplicationState, state_dir: &Path) {
+    if let Err(e) = store::save(&state_dir.join(ONE_OFFS_FILE), &app_state.one_offs) {
+        eprintln!("Failed to save pending block starts: {e}");
+    }
+}
+
+/// Today's local time for a schedule time in another zone, marked when it
+/// falls on a different day there, e.g. "03:00 (+1d)".
+fn local_equivalent(
+    time: &str,
+    zone: chrono_tz::Tz,
+    now: chrono::DateTime<chrono::Local>,
+) -> Option<String> {
+    let time = timespec::parse_time_of_day(time).ok()?;
+    let zone_date = now.with_timezone(&zone).date_naive();
+    let local = timespec::in_zone(&zone, zone_date.and_time(time))?.with_timezone(&chrono::Local);
+    Some(match (local.date_naive() - zone_date).num_days() {
+        0 => local.format("%H:%M").to_string(),
+        days => format!("{} ({days:+}d)", local.format("%H:%M")),
+    })
+}
+
+fn save_allowance_usage(app_state: &ApplicationState, state_dir: &Path) {
+    let usage = allowance_usage(app_state);
+    // No block has an allowance
+    if usage.is_empty() {
+        return;
+    }
+    if let Err(e) = store::save(&state_dir.join(ALLOWANCE_USAGE_FILE), &usage) {
+        eprintln!("Failed to save allowance usage: {e}");
+    }
+}
+
+fn save_suspended(suspended: &[Suspended], state_dir: &Path) {
+    if let Err(e) = store::save(&state_dir.join(SUSPENDED_FILE), &suspended) {
+        eprintln!("Failed to save suspended applications: {e}");
+    }
+}
+
+fn save_schedule_overlay(app_state: &ApplicationState, state_dir: &Path) {
+    if let Err(e) = store::save(&state_dir.join(SCHEDULE_OVERLAY_FILE), &app_state.schedule_overlay)
+    {
+        eprintln!("Failed to save schedule changes: {e}");
+    }
+}
+
+/// A file's modification time and size, or None while it's missing, to
+/// notice when a calendar is rewritten.
+fn file_version(path: &Path) -> Option<(SystemTime, u64)> {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+fn parse_timestamp(value: &Value) -> Option<chrono::DateTime<chrono::Local>> {
+    value
+        .as_i64()
+        .and_then(|ts| chrono::Local.timestamp_opt(ts, 0).single())
+}
+
 fn serialize_state(app_state: &ApplicationState) -> Vec<u8> {
     let string_map: HashMap<String, &Block> = app_state
         .blocks
@@ -269,3 +985,228 @@ async fn send_prefixed_bytes(stream: &mut UnixStream, message: &[u8]) -> io::Res
     stream.write_all(message).await?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{DateTime, Duration, Local, NaiveDate};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::sync::oneshot;
+
+    static NEXT_DAEMON: AtomicUsize = AtomicUsize::new(0);
+    /// How long, in real time, to wait for the daemon before failing.
+    const WAIT_LIMIT: std::time::Duration = std::time::Duration::from_secs(5);
+
+    /// A daemon on a fake clock, with its own sockets and state directory.
+    struct TestDaemon {
+        clock: Clock,
+        dir: PathBuf,
+        task: tokio::task::JoinHandle<()>,
+        shutdown: Option<oneshot::Sender<()>>,
+    }
+
+    impl TestDaemon {
+        async fn start(config: &str, now: DateTime<Local>) -> TestDaemon {
+            let id = NEXT_DAEMON.fetch_add(1, Ordering::Relaxed);
+            let dir =
+                std::env::temp_dir().join(format!("shire-daemon-{}-{id}", std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+
+            let clock = Clock::fake(now);
+            let (task, shutdown) = Self::spawn(config, &clock, &dir).await;
+            TestDaemon {
+                clock,
+                dir,
+                task,
+                shutdown: Some(shutdown),
+            }
+        }
+
+        /// Shuts the daemon down, waiting for all of its tasks to stop, and
+        /// starts another on the same clock and state directory, as after a
+        /// service restart.
+        async fn restart(&mut self, config: &str) {
+            if let Some(shutdown) = self.shutdown.take() {
+                let _ = shutdown.send(());
+            }
+            tokio::time::timeout(WAIT_LIMIT, &mut self.task)
+                .await
+                .expect("the daemon didn't shut down")
+                .unwrap();
+            let _ = fs::remove_file(self.dir.join("cli.sock"));
+            let (task, shutdown) = Self::spawn(config, &self.clock, &self.dir).await;
+            self.task = task;
+            self.shutdown = Some(shutdown);
+        }
+
+        async fn spawn(
+            config: &str,
+            clock: &Clock,
+            dir: &Path,
+        ) -> (tokio::task::JoinHandle<()>, oneshot::Sender<()>) {
+            let config = config::parse_config_str(config, dir).unwrap();
+            let env = DaemonEnv {
+                clock: clock.clone(),
+                cli_socket: dir.join("cli.sock"),
+                bridge_socket: dir.join("bridge.sock"),
+                state_dir: dir.to_path_buf(),
+            };
+            let (shutdown, shutdown_received) = oneshot::channel();
+            let task = tokio::spawn(run_daemon(config, env, async {
+                let _ = shutdown_received.await;
+            }));
+            let listening = async {
+                while !dir.join("cli.sock").exists() {
+                    tokio::task::yield_now().await;
+                }
+            };
+            tokio::time::timeout(WAIT_LIMIT, listening)
+                .await
+                .expect("the daemon didn't start listening");
+            (task, shutdown)
+        }
+
+        async fn request(&self, request: Value) -> Value {
+            let mut stream = UnixStream::connect(self.dir.join("cli.sock")).await.unwrap();
+            send_length_prefixed_message_async(&mut stream, request.to_string().as_bytes())
+                .await
+                .unwrap();
+            let response = recv_length_prefixed_message_async(&mut stream).await.unwrap();
+            serde_json::from_slice(&response).unwrap()
+        }
+
+        async fn is_blocked(&self, block: &str) -> bool {
+            let blocks = self.request(serde_json::json!({ "action": "list_blocks" })).await;
+            blocks["blocks"][block]["state"] == "Blocked"
+        }
+
+        /// Waits until the daemon reports `block` as blocked or not, which
+        /// it may only do once its tasks have caught up with the clock.
+        async fn expect_blocked(&self, block: &str, blocked: bool, context: &str) {
+            let reached = async {
+                while self.is_blocked(block).await != blocked {
+                    tokio::task::yield_now().await;
+                }
+            };
+            if tokio::time::timeout(WAIT_LIMIT, reached).await.is_err() {
+                panic!("{context}: expected '{block}' to be blocked: {blocked}");
+            }
+        }
+
+        fn advance_to(&self, at: DateTime<Local>) {
+            self.clock.advance(at - self.clock.now());
+        }
+    }
+
+    impl Drop for TestDaemon {
+        fn drop(&mut self) {
+            self.task.abort();
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    fn local(date: NaiveDate, hour: u32, minute: u32) -> DateTime<Local> {
+        date.and_hms_opt(hour, minute, 0).unwrap().and_local_timezone(Local).unwrap()
+    }
+
+    const CONFIG: &str = r#"
+        [[blocks]]
+        name = "work"
+
+        [[schedule]]
+        block = "work"
+        days = ["Mon", "Tue", "Wed", "Thu", "Fri"]
+        start = "9:00"
+        end = "17:00"
+    "#;
+
+    #[tokio::test]
+    async fn test_schedule_follows_the_clock_across_weeks() {
+        // Both the EU and the US leave summer time within these weeks
+        let monday = NaiveDate::from_ymd_opt(2026, 10, 19).unwrap();
+        let daemon = TestDaemon::start(CONFIG, local(monday, 8, 0)).await;
+
+        for day in 0..21 {
+            let date = monday + Duration::days(day);
+            let weekday = day % 7 < 5;
+            daemon.advance_to(local(date, 8, 59));
+            daemon.expect_blocked("work", false, &format!("{date} 08:59")).await;
+            daemon.advance_to(local(date, 9, 0));
+            daemon.expect_blocked("work", weekday, &format!("{date} 09:00")).await;
+            daemon.advance_to(local(date, 16, 59));
+            daemon.expect_blocked("work", weekday, &format!("{date} 16:59")).await;
+            daemon.advance_to(local(date, 17, 0));
+            daemon.expect_blocked("work", false, &format!("{date} 17:00")).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_locks_timers_and_one_offs_follow_the_clock() {
+        let saturday = NaiveDate::from_ymd_opt(2026, 10, 24).unwrap();
+        let now = local(saturday, 10, 0);
+        let daemon = TestDaemon::start(CONFIG, now).await;
+
+        let started = daemon
+            .request(serde_json::json!({
+                "action": "start_block",
+                "name": "work",
+                "until": (now + Duration::hours(3)).timestamp(),
+                "lock_until": (now + Duration::hours(2)).timestamp(),
+            }))
+            .await;
+        assert_eq!(started["status"], "started");
+
+        let stop = serde_json::json!({ "action": "stop_block", "name": "work" });
+        daemon.advance_to(now + Duration::minutes(119));
+        assert_eq!(daemon.request(stop.clone()).await["status"], "error");
+        daemon.advance_to(now + Duration::hours(3));
+        daemon.expect_blocked("work", false, "timer ended").await;
+
+        // Sunday evening, long after the daemon last heard from anyone
+        let at = local(saturday + Duration::days(1), 20, 0);
+        let scheduled = daemon
+            .request(serde_json::json!({
+                "action": "schedule_block",
+                "name": "work",
+                "at": at.timestamp(),
+                "until": (at + Duration::hours(1)).timestamp(),
+            }))
+            .await;
+        assert_eq!(scheduled["status"], "scheduled");
+        daemon.advance_to(at - Duration::minutes(1));
+        daemon.expect_blocked("work", false, "before the booked start").await;
+        daemon.advance_to(at);
+        daemon.expect_blocked("work", true, "booked start").await;
+        daemon.advance_to(at + Duration::hours(1));
+        daemon.expect_blocked("work", false, "booked end").await;
+    }
+
+    #[tokio::test]
+    async fn test_allowance_usage_survives_restarts() {
+        let config = r#"
+            [[blocks]]
+            name = "feeds"
+            blacklist = ["example.com"]
+            daily_allowance = "2m"
+        "#;
+        let monday = NaiveDate::from_ymd_opt(2026, 10, 19).unwrap();
+        let mut daemon = TestDaemon::start(config, local(monday, 10, 0)).await;
+        let report = serde_json::json!({
+            "action": "report_activity",
+            "url": "https://example.com/",
+            "seconds": 60,
+        });
+
+        daemon.request(report.clone()).await;
+        daemon.restart(config).await;
+        daemon.expect_blocked("feeds", false, "a minute used").await;
+        daemon.request(report).await;
+        daemon.expect_blocked("feeds", true, "allowance used up").await;
+
+        daemon.restart(config).await;
+        daemon.expect_blocked("feeds", true, "restarted after using it up").await;
+        daemon.advance_to(local(monday + Duration::days(1), 0, 1));
+        daemon.expect_blocked("feeds", false, "the next day").await;
+    }
+}
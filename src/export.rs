@@ -0,0 +1,211 @@
+use crate::config::{Block, Config};
+use clap::ValueEnum;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ExportFormat {
+    /// A hosts-file section for system-level blocking
+    Hosts,
+    /// uBlock Origin static filters
+    Ublock,
+    /// Canonical JSON for scripts
+    Json,
+    /// A [[blocks]] TOML snippet with includes and files resolved
+    Toml,
+}
+
+/// A block as exported: patterns are resolved, deduplicated and sorted so
+/// the output is stable across runs.
+#[derive(Debug, Serialize)]
+struct ExportedBlock {
+    name: String,
+    whitelist: Vec<String>,
+    blacklist: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct ExportedBlocks {
+    blocks: Vec<ExportedBlock>,
+}
+
+/// Renders the selected blocks (all of them if `selected` is empty) in the
+/// given format.
+pub fn export(
+    config: &Config,
+    format: ExportFormat,
+    selected: &[String],
+) -> Result<String, String> {
+    if let Some(missing) = selected
+        .iter()
+        .find(|name| !config.blocks.iter().any(|b| &b.name == *name))
+    {
+        return Err(format!("Block '{missing}' not found"));
+    }
+
+    let mut blocks: Vec<ExportedBlock> = config
+        .blocks
+        .iter()
+        // Private blocks are only exported when asked for by name
+        .filter(|b| {
+            if selected.is_empty() {
+                !b.private
+            } else {
+                selected.contains(&b.name)
+            }
+        })
+        .map(exported_block)
+        .collect();
+    blocks.sort_by(|a, b| a.name.cmp(&b.name));
+
+    match format {
+        ExportFormat::Hosts => Ok(to_hosts(&blocks)),
+        ExportFormat::Ublock => Ok(to_ublock(&blocks)),
+        ExportFormat::Json => serde_json::to_string_pretty(&ExportedBlocks { blocks })
+            .map(|json| json + "\n")
+            .map_err(|e| e.to_string()),
+        ExportFormat::Toml => {
+            toml::to_string(&ExportedBlocks { blocks }).map_err(|e| e.to_string())
+        }
+    }
+}
+
+fn exported_block(block: &Block) -> ExportedBlock {
+    let sorted = |patterns: &Option<Vec<String>>| -> Vec<String> {
+        patterns
+            .iter()
+            .flatten()
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .cloned()
+            .collect()
+    };
+
+    ExportedBlock {
+        name: block.name.clone(),
+        whitelist: sorted(&block.whitelist),
+        blacklist: sorted(&block.blacklist),
+    }
+}
+
+fn to_hosts(blocks: &[ExportedBlock]) -> String {
+    let names: Vec<&str> = blocks.iter().map(|b| b.name.as_str()).collect();
+    let mut domains = BTreeSet::new();
+    let mut skipped = BTreeMap::new();
+
+    for block in blocks {
+        for pattern in &block.blacklist {
+            // Hosts files can only block whole domains
+            if pattern.contains('/') || pattern.contains('*') {
+                skipped.insert(pattern.as_str(), "not a plain domain");
+                continue;
+            }
+            // A whitelist entry for the bare domain means it must stay reachable
+            if block
+                .whitelist
+                .iter()
+                .any(|w| w.trim_end_matches('*') == pattern)
+            {
+                skipped.insert(pattern.as_str(), "whitelisted");
+                continue;
+            }
+            domains.insert(pattern.clone());
+            if !pattern.starts_with("www.") {
+                domains.insert(format!("www.{pattern}"));
+            }
+        }
+    }
+
+    let mut out = format!("# BEGIN shire_blocker ({})\n", names.join(", "));
+    for (pattern, reason) in skipped {
+        out.push_str(&format!("# skipped, {reason}: {pattern}\n"));
+    }
+    for domain in domains {
+        out.push_str(&format!("0.0.0.0 {domain}\n"));
+    }
+    out.push_str("# END shire_blocker\n");
+    out
+}
+
+fn to_ublock(blocks: &[ExportedBlock]) -> String {
+    let mut out = String::from("! Title: Shire Blocker\n");
+
+    for block in blocks {
+        out.push_str(&format!("\n! Block: {}\n", block.name));
+        for pattern in &block.blacklist {
+            out.push_str(&format!("{}\n", ublock_filter(pattern)));
+        }
+        for pattern in &block.whitelist {
+            out.push_str(&format!("@@{}\n", ublock_filter(pattern)));
+        }
+    }
+
+    out
+}
+
+/// Anchors a shire URL prefix to the start of a domain. A bare domain also
+/// gets a separator so "x.com" doesn't match "x.com.evil.org".
+fn ublock_filter(pattern: &str) -> String {
+    if pattern.contains('/') || pattern.ends_with('*') {
+        format!("||{pattern}")
+    } else {
+        format!("||{pattern}^")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::parse_config_str;
+    use std::path::Path;
+
+    fn config() -> Config {
+        parse_config_str(
+            r#"
+            schedule = []
+
+            [[blocks]]
+            name = "feeds"
+            blacklist = ["youtube.com", "linkedin.com/feed", "reddit.com"]
+            whitelist = ["youtube.com/watch?v=*", "reddit.com"]
+
+            [[blocks]]
+            name = "finance"
+            blacklist = ["robinhood.com"]
+            "#,
+            Path::new("."),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_export_hosts_skips_paths_and_whitelisted_domains() {
+        let hosts = export(&config(), ExportFormat::Hosts, &[]).unwrap();
+        assert_eq!(
+            hosts,
+            "# BEGIN shire_blocker (feeds, finance)\n\
+             # skipped, not a plain domain: linkedin.com/feed\n\
+             # skipped, whitelisted: reddit.com\n\
+             0.0.0.0 robinhood.com\n\
+             0.0.0.0 www.robinhood.com\n\
+             0.0.0.0 www.youtube.com\n\
+             0.0.0.0 youtube.com\n\
+             # END shire_blocker\n"
+        );
+    }
+
+    #[test]
+    fn test_export_ublock_filters() {
+        let filters = export(&config(), ExportFormat::Ublock, &["feeds".to_string()]).unwrap();
+        assert!(filters.contains("||youtube.com^\n"));
+        assert!(filters.contains("||linkedin.com/feed\n"));
+        assert!(filters.contains("@@||youtube.com/watch?v=*\n"));
+        assert!(!filters.contains("robinhood"));
+    }
+
+    #[test]
+    fn test_export_unknown_block() {
+        let error = export(&config(), ExportFormat::Json, &["nope".to_string()]).unwrap_err();
+        assert_eq!(error, "Block 'nope' not found");
+    }
+}
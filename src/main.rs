@@ -1,13 +1,24 @@
+mod apps;
+mod blocklist;
+mod calendar;
+mod clock;
 mod commands;
 mod config;
 mod daemon;
+mod export;
+mod private;
 mod service;
+mod simulate;
 mod state;
+mod store;
+mod timespec;
+mod validate;
 use clap::{Parser, Subcommand};
 use commands::list_blocks;
 use serde_json::json;
 use std::collections::HashMap;
 use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
 use shire_blocker::CLI_SOCKET_PATH;
 
 use crate::commands::*;
@@ -33,6 +44,16 @@ enum Commands {
         #[command(subcommand)]
         action: BlockAction,
     },
+    /// Run focus sessions defined in the config
+    Session {
+        #[command(subcommand)]
+        action: SessionAction,
+    },
+    /// Alternate blocks between work and break phases
+    Pomodoro {
+        #[command(subcommand)]
+        action: PomodoroAction,
+    },
     /// Manage the block scheduler
     Schedule {
         #[command(subcommand)]
@@ -43,6 +64,22 @@ enum Commands {
         #[command(subcommand)]
         action: ServiceAction,
     },
+    /// Work with the configuration file
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+    /// Export blocks for use by other tools
+    Export {
+        #[arg(long, value_enum)]
+        format: export::ExportFormat,
+        /// Only export these blocks (repeatable); defaults to all blocks
+        #[arg(long = "block", value_name = "NAME")]
+        blocks: Vec<String>,
+        /// Config file to read instead of the default one
+        #[arg(long)]
+        config: Option<String>,
+    },
     /// Launch the daemon
     Daemon {
         #[arg(long)]
@@ -57,23 +94,174 @@ enum BlockAction {
     /// Start a block
     Start {
         name: String,
-        #[arg(long)]
-        // Need to make this more obvious to users
-        lock: Option<String>, // e.g. duration
+        /// Stop the block automatically after this long (e.g. 90m, 1h30m)
+        #[arg(long = "for", value_name = "DURATION", value_parser = timespec::parse_duration, conflicts_with = "until")]
+        duration: Option<chrono::Duration>,
+        /// Stop the block automatically at this time (e.g. 17:30, 5pm, "tomorrow 09:00")
+        #[arg(long, value_name = "TIME")]
+        until: Option<String>,
+        /// Prevent the block from being stopped for this long (e.g. 45m, "2 hours")
+        #[arg(long, value_name = "DURATION", value_parser = timespec::parse_duration)]
+        lock: Option<chrono::Duration>,
+        /// Start the block later instead, e.g. "2026-10-20 14:00"; --for,
+        /// --until and --lock then count from this time
+        #[arg(long, value_name = "TIME", value_parser = timespec::parse_deadline_from_now)]
+        at: Option<chrono::DateTime<chrono::Local>>,
+    },
+    /// List block starts booked with --at
+    Pending,
+    /// Cancel a block start booked with --at
+    Unschedule {
+        /// The id shown by `shire block pending`
+        id: u32,
     },
     /// Stop a block
     Stop { name: String },
+    /// Cancel a pending delayed unblock
+    Cancel { name: String },
+    /// Lock a block so it cannot be stopped for a while
     Lock {
         name: String,
-        // Need to make this more obvious to users
-        lock: Option<String>, // e.g. duration
+        #[arg(value_name = "DURATION", value_parser = timespec::parse_duration)]
+        lock: chrono::Duration,
     },
 }
 
+#[derive(Subcommand)]
+enum SessionAction {
+    /// Start all blocks of a session at once
+    Start {
+        name: String,
+        /// Override the session's default duration (e.g. 2h)
+        #[arg(long = "for", value_name = "DURATION", value_parser = timespec::parse_duration, conflicts_with = "until")]
+        duration: Option<chrono::Duration>,
+        /// End the session at this time instead (e.g. 17:30)
+        #[arg(long, value_name = "TIME", value_parser = timespec::parse_deadline_from_now)]
+        until: Option<chrono::DateTime<chrono::Local>>,
+    },
+    /// Show the running session
+    Status,
+    /// End the running session early (not possible while it is locked)
+    Stop,
+}
+
+#[derive(Subcommand)]
+enum PomodoroAction {
+    /// Start a pomodoro over the given blocks
+    Start {
+        /// Length of each work phase, during which the blocks are active
+        #[arg(long, value_name = "DURATION", default_value = "25m", value_parser = timespec::parse_duration)]
+        work: chrono::Duration,
+        /// Length of each break, during which the blocks are inactive
+        #[arg(long = "break", value_name = "DURATION", default_value = "5m", value_parser = timespec::parse_duration)]
+        break_duration: chrono::Duration,
+        /// Number of work phases
+        #[arg(long, default_value_t = 4)]
+        cycles: u32,
+        /// Comma-separated list of blocks to cycle
+        #[arg(long, value_delimiter = ',', required = true)]
+        blocks: Vec<String>,
+    },
+    /// Show the current phase and its remaining time
+    Status,
+    /// Stop the running pomodoro and unblock its blocks
+    Stop,
+}
+
 #[derive(Subcommand)]
 enum ScheduleAction {
     /// List the schedule
     List,
+    /// Add a schedule without editing the config file
+    Add {
+        block: String,
+        /// Comma-separated days, e.g. Mon-Fri or Mon,Wed
+        #[arg(long, value_delimiter = ',', required_unless_present = "cron")]
+        days: Vec<String>,
+        /// When the block starts (e.g. 09:00, 5pm)
+        #[arg(long, required_unless_present = "cron")]
+        start: Option<String>,
+        /// When the block ends
+        #[arg(long, required_unless_present = "cron")]
+        end: Option<String>,
+        /// Start the block at each match of a cron expression instead (e.g. "0 9 * * MON#1")
+        #[arg(long, conflicts_with_all = ["days", "start", "end"], requires = "duration")]
+        cron: Option<String>,
+        /// How long each cron window lasts (e.g. 30m)
+        #[arg(long, requires = "cron")]
+        duration: Option<String>,
+        /// Name for the schedule, so exceptions can refer to it
+        #[arg(long)]
+        name: Option<String>,
+        /// IANA time zone the days and times are in (e.g. America/New_York)
+        #[arg(long)]
+        timezone: Option<String>,
+    },
+    /// Remove a schedule added with `shire schedule add`
+    Remove {
+        /// The id shown by `shire schedule list`
+        id: u32,
+    },
+    /// Skip a block's scheduled starts for a while
+    Pause {
+        block: String,
+        /// When the schedule resumes (e.g. 17:30, "tomorrow 09:00")
+        #[arg(long, value_name = "TIME", value_parser = timespec::parse_deadline_from_now)]
+        until: chrono::DateTime<chrono::Local>,
+    },
+    /// Show when the config's schedules would switch each block on and off
+    Simulate {
+        /// Start of the range: a date (2026-10-19) or date and time ("2026-10-19 09:00")
+        #[arg(long, value_name = "TIME", value_parser = timespec::parse_moment)]
+        from: chrono::DateTime<chrono::Local>,
+        /// End of the range, in the same formats
+        #[arg(long, value_name = "TIME", value_parser = timespec::parse_moment)]
+        to: chrono::DateTime<chrono::Local>,
+        /// Config file to simulate instead of the default one
+        #[arg(long)]
+        config: Option<String>,
+        /// Compare with another config file and show only what differs
+        #[arg(long, value_name = "OTHER_CONFIG")]
+        diff: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum ConfigAction {
+    /// Encrypt a private config fragment into conf.d
+    Encrypt {
+        /// Plain TOML file holding the private blocks
+        input: String,
+        /// Where to write it instead of conf.d/<input>.enc
+        #[arg(long)]
+        output: Option<String>,
+        /// Config file whose directory holds the key and conf.d
+        #[arg(long)]
+        config: Option<String>,
+    },
+    /// Print a decrypted config fragment, e.g. to edit it
+    Decrypt {
+        /// Encrypted fragment to read
+        input: String,
+        /// Config file whose directory holds the key
+        #[arg(long)]
+        config: Option<String>,
+    },
+    /// Write a commented starter config file
+    Init {
+        /// Where to write the file instead of the default location
+        #[arg(long)]
+        config: Option<String>,
+        /// Replace an existing file
+        #[arg(long)]
+        force: bool,
+    },
+    /// Check a config file for errors without starting the daemon
+    Validate {
+        /// Config file to check instead of the default one
+        #[arg(long)]
+        config: Option<String>,
+    },
 }
 
 #[derive(Subcommand)]
@@ -101,16 +289,61 @@ async fn main() {
                     .expect("Failed to connect to the shire daemon at {CLI_SOCKET_PATH}: {e}");
                 list_blocks(&mut stream).expect("Failed to list available blocks");
             }
-            BlockAction::Start { name, lock } => {
+            BlockAction::Start {
+                name,
+                duration,
+                until,
+                lock,
+                at,
+            } => {
+                let start = at.unwrap_or_else(chrono::Local::now);
+                let until = until
+                    .map(|until| match at {
+                        Some(at) => timespec::parse_end(&until, at),
+                        None => timespec::parse_deadline(&until, start),
+                    })
+                    .transpose()
+                    .unwrap_or_else(|e| {
+                        eprintln!("{e}");
+                        std::process::exit(1);
+                    });
+                let end = duration.map(|d| time_after(start, d)).or(until);
+                let lock_until = lock.map(|lock| time_after(start, lock));
                 let mut stream = UnixStream::connect(CLI_SOCKET_PATH)
                     .expect("Failed to connect to the shire service socket at {CLI_SOCKET_PATH}: {e}");
 
                 let mut params = HashMap::new();
                 params.insert("name", json!(name));
-                if let Some(lock_str) = lock {
-                    params.insert("lock", json!(lock_str));
+                if let Some(end) = end {
+                    params.insert("until", json!(end.timestamp()));
                 }
-                send_action_with_params(&mut stream, "start_block", Some(params)).unwrap();
+                if let Some(lock_until) = lock_until {
+                    params.insert("lock_until", json!(lock_until.timestamp()));
+                }
+                let action = match at {
+                    Some(at) => {
+                        params.insert("at", json!(at.timestamp()));
+                        "schedule_block"
+                    }
+                    None => "start_block",
+                };
+                let response = send_action_with_params(&mut stream, action, Some(params)).unwrap();
+                report_response(&response);
+            }
+            BlockAction::Pending => {
+                let mut stream = UnixStream::connect(CLI_SOCKET_PATH)
+                    .expect("Failed to connect to the shire service socket at {CLI_SOCKET_PATH}: {e}");
+                list_one_offs(&mut stream).expect("Failed to list pending block starts");
+            }
+            BlockAction::Unschedule { id } => {
+                let mut stream = UnixStream::connect(CLI_SOCKET_PATH)
+                    .expect("Failed to connect to the shire service socket at {CLI_SOCKET_PATH}: {e}");
+
+                let mut params = HashMap::new();
+                params.insert("id", json!(id));
+                let response =
+                    send_action_with_params(&mut stream, "cancel_one_off", Some(params)).unwrap();
+                report_response(&response);
             }
             BlockAction::Stop { name } => {
                 let mut stream = UnixStream::connect(CLI_SOCKET_PATH)
@@ -118,24 +351,168 @@ async fn main() {
 
                 let mut params = HashMap::new();
                 params.insert("name", json!(name));
-                send_action_with_params(&mut stream, "stop_block", Some(params)).unwrap();
+                let response =
+                    send_action_with_params(&mut stream, "stop_block", Some(params)).unwrap();
+                report_response(&response);
+            }
+            BlockAction::Cancel { name } => {
+                let mut stream = UnixStream::connect(CLI_SOCKET_PATH)
+                    .expect("Failed to connect to the shire service socket at {CLI_SOCKET_PATH}: {e}");
+
+                let mut params = HashMap::new();
+                params.insert("name", json!(name));
+                let response =
+                    send_action_with_params(&mut stream, "cancel_unblock", Some(params)).unwrap();
+                report_response(&response);
             }
             BlockAction::Lock { name, lock } => {
+                let lock_until = time_after(chrono::Local::now(), lock);
                 let mut stream = UnixStream::connect(CLI_SOCKET_PATH)
                     .expect("Failed to connect to the shire service socket at {CLI_SOCKET_PATH}: {e}");
 
                 let mut params = HashMap::new();
                 params.insert("name", json!(name));
-                params.insert("lock", json!(lock));
-                send_action_with_params(&mut stream, "lock_block", Some(params)).unwrap();
+                params.insert("lock_until", json!(lock_until.timestamp()));
+                let response =
+                    send_action_with_params(&mut stream, "lock_block", Some(params)).unwrap();
+                report_response(&response);
             }
         },
-        Commands::Schedule { action } => match action {
-            // TODO: Need to implement listing of schedule
-            ScheduleAction::List => {
-                println!("Listing the block schedule...");
+        Commands::Session { action } => {
+            let mut stream = UnixStream::connect(CLI_SOCKET_PATH)
+                .expect("Failed to connect to the shire service socket at {CLI_SOCKET_PATH}: {e}");
+
+            match action {
+                SessionAction::Start {
+                    name,
+                    duration,
+                    until,
+                } => {
+                    let mut params = HashMap::new();
+                    params.insert("name", json!(name));
+                    let end = duration.map(|d| time_after(chrono::Local::now(), d)).or(until);
+                    if let Some(end) = end {
+                        params.insert("until", json!(end.timestamp()));
+                    }
+                    let response =
+                        send_action_with_params(&mut stream, "start_session", Some(params))
+                            .unwrap();
+                    report_response(&response);
+                }
+                SessionAction::Status => {
+                    session_status(&mut stream).expect("Failed to get session status");
+                }
+                SessionAction::Stop => {
+                    let response =
+                        send_action_with_params(&mut stream, "stop_session", None).unwrap();
+                    report_response(&response);
+                }
             }
-        },
+        }
+        Commands::Pomodoro { action } => {
+            let mut stream = UnixStream::connect(CLI_SOCKET_PATH)
+                .expect("Failed to connect to the shire service socket at {CLI_SOCKET_PATH}: {e}");
+
+            match action {
+                PomodoroAction::Start {
+                    work,
+                    break_duration,
+                    cycles,
+                    blocks,
+                } => {
+                    let mut params = HashMap::new();
+                    params.insert("blocks", json!(blocks));
+                    params.insert("work_secs", json!(work.num_seconds()));
+                    params.insert("break_secs", json!(break_duration.num_seconds()));
+                    params.insert("cycles", json!(cycles));
+                    let response =
+                        send_action_with_params(&mut stream, "start_pomodoro", Some(params))
+                            .unwrap();
+                    report_response(&response);
+                }
+                PomodoroAction::Status => {
+                    pomodoro_status(&mut stream).expect("Failed to get pomodoro status");
+                }
+                PomodoroAction::Stop => {
+                    let response =
+                        send_action_with_params(&mut stream, "stop_pomodoro", None).unwrap();
+                    report_response(&response);
+                }
+            }
+        }
+        Commands::Schedule { action } => {
+            let connect = || {
+                UnixStream::connect(CLI_SOCKET_PATH).expect(
+                    "Failed to connect to the shire service socket at {CLI_SOCKET_PATH}: {e}",
+                )
+            };
+
+            match action {
+                ScheduleAction::List => {
+                    list_schedule(&mut connect()).expect("Failed to list the schedule");
+                }
+                ScheduleAction::Add {
+                    block,
+                    days,
+                    start,
+                    end,
+                    cron,
+                    duration,
+                    name,
+                    timezone,
+                } => {
+                    let mut stream = connect();
+                    let mut params = HashMap::new();
+                    params.insert(
+                        "schedule",
+                        json!({
+                            "name": name,
+                            "block": block,
+                            "days": days,
+                            "start": start.unwrap_or_default(),
+                            "end": end.unwrap_or_default(),
+                            "cron": cron,
+                            "duration": duration,
+                            "timezone": timezone,
+                        }),
+                    );
+                    let response =
+                        send_action_with_params(&mut stream, "add_schedule", Some(params))
+                            .unwrap();
+                    report_response(&response);
+                }
+                ScheduleAction::Remove { id } => {
+                    let mut stream = connect();
+                    let mut params = HashMap::new();
+                    params.insert("id", json!(id));
+                    let response =
+                        send_action_with_params(&mut stream, "remove_schedule", Some(params))
+                            .unwrap();
+                    report_response(&response);
+                }
+                ScheduleAction::Pause { block, until } => {
+                    let mut stream = connect();
+                    let mut params = HashMap::new();
+                    params.insert("name", json!(block));
+                    params.insert("until", json!(until.timestamp()));
+                    let response =
+                        send_action_with_params(&mut stream, "pause_schedule", Some(params))
+                            .unwrap();
+                    report_response(&response);
+                }
+                ScheduleAction::Simulate {
+                    from,
+                    to,
+                    config,
+                    diff,
+                } => {
+                    if let Err(e) = simulate_schedule(config, diff, from, to) {
+                        eprintln!("{e}");
+                        std::process::exit(1);
+                    }
+                }
+            }
+        }
         Commands::Service { action } => match action {
             ServiceAction::Start => {
                 println!("Starting shire service (install and start daemon)...");
@@ -175,8 +552,123 @@ async fn main() {
                 }
             }
         },
+        Commands::Config { action } => match action {
+            ConfigAction::Init { config, force } => {
+                let path = match config {
+                    Some(path) => PathBuf::from(path),
+                    None => config::user_config_path().unwrap_or_else(|e| {
+                        eprintln!("Failed to locate config file: {e}");
+                        std::process::exit(1);
+                    }),
+                };
+                match config::write_starter_config(&path, force) {
+                    Ok(()) => println!("Wrote a starter config to {}", path.display()),
+                    Err(e) => {
+                        eprintln!("{e}");
+                        std::process::exit(1);
+                    }
+                }
+            }
+            ConfigAction::Encrypt {
+                input,
+                output,
+                config,
+            } => {
+                let config_path = config_path(config);
+                let config_dir = config_path.parent().unwrap_or(Path::new("."));
+                let input = PathBuf::from(input);
+                let output = output.map(PathBuf::from).unwrap_or_else(|| {
+                    let mut name = input.file_name().unwrap_or_default().to_os_string();
+                    name.push(".enc");
+                    config_dir.join("conf.d").join(name)
+                });
+                match private::encrypt_file(&input, &output, config_dir) {
+                    Ok(created) => {
+                        if let Some(keyfile) = created {
+                            println!(
+                                "Created a new key at {}. Back it up: without it the fragment can't be read.",
+                                keyfile.display()
+                            );
+                        }
+                        println!(
+                            "Wrote {}. You can now delete {}.",
+                            output.display(),
+                            input.display()
+                        );
+                    }
+                    Err(e) => {
+                        eprintln!("{e}");
+                        std::process::exit(1);
+                    }
+                }
+            }
+            ConfigAction::Decrypt { input, config } => {
+                let config_path = config_path(config);
+                let config_dir = config_path.parent().unwrap_or(Path::new("."));
+                match private::read_encrypted_file(Path::new(&input), config_dir) {
+                    Ok(plaintext) => print!("{plaintext}"),
+                    Err(e) => {
+                        eprintln!("{e}");
+                        std::process::exit(1);
+                    }
+                }
+            }
+            ConfigAction::Validate { config } => {
+                let path = config_path(config);
+                match validate::validate_file(&path) {
+                    Ok(warnings) => {
+                        for warning in warnings {
+                            eprintln!("{warning}");
+                        }
+                        println!("{}: configuration is valid", path.display());
+                    }
+                    Err(report) => {
+                        eprintln!("{report}");
+                        std::process::exit(1);
+                    }
+                }
+            }
+        },
+        Commands::Export {
+            format,
+            blocks,
+            config,
+        } => {
+            let output = config::parse_config(config)
+                .map_err(|e| e.to_string())
+                .and_then(|config| export::export(&config, format, &blocks));
+            match output {
+                Ok(output) => print!("{output}"),
+                Err(e) => {
+                    eprintln!("Failed to export blocks: {e}");
+                    std::process::exit(1);
+                }
+            }
+        }
         Commands::Daemon { config } => {
             start_daemon(config).await;
         }
     }
 }
+
+/// `start` plus a `--for` or `--lock` duration, exiting when that's past
+/// the last time chrono can represent.
+fn time_after(
+    start: chrono::DateTime<chrono::Local>,
+    duration: chrono::Duration,
+) -> chrono::DateTime<chrono::Local> {
+    start.checked_add_signed(duration).unwrap_or_else(|| {
+        eprintln!("Invalid duration: time out of range");
+        std::process::exit(1);
+    })
+}
+
+fn config_path(config: Option<String>) -> PathBuf {
+    match config {
+        Some(path) => PathBuf::from(path),
+        None => config::default_config_path().unwrap_or_else(|e| {
+            eprintln!("Failed to locate config file: {e}");
+            std::process::exit(1);
+        }),
+    }
+}
@@ -0,0 +1,165 @@
+use argon2::Argon2;
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use std::env;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+
+/// Encrypted fragments are `conf.d/*.toml.enc` files.
+pub const ENCRYPTED_EXTENSION: &str = "enc";
+/// Takes precedence over the keyfile, e.g. for one-off `shire config` runs.
+pub const PASSPHRASE_VAR: &str = "SHIRE_PASSPHRASE";
+
+// File layout: MAGIC, then a random salt and nonce, then the ciphertext.
+const MAGIC: &[u8] = b"shire-encrypted-v1\n";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+pub fn is_encrypted(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext == ENCRYPTED_EXTENSION)
+}
+
+/// The keyfile lives next to the main config file.
+pub fn keyfile_path(config_dir: &Path) -> PathBuf {
+    config_dir.join("private.key")
+}
+
+/// Reads the secret from `SHIRE_PASSPHRASE`, or from the keyfile.
+pub fn load_secret(config_dir: &Path) -> Result<Vec<u8>, String> {
+    if let Ok(passphrase) = env::var(PASSPHRASE_VAR)
+        && !passphrase.is_empty()
+    {
+        return Ok(passphrase.into_bytes());
+    }
+
+    let keyfile = keyfile_path(config_dir);
+    match fs::read_to_string(&keyfile) {
+        Ok(key) => Ok(key.trim().as_bytes().to_vec()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Err(format!(
+            "No key for encrypted config: set {PASSPHRASE_VAR} or create '{}'.",
+            keyfile.display()
+        )),
+        Err(e) => Err(format!("Failed to read '{}': {}", keyfile.display(), e)),
+    }
+}
+
+/// Writes a new random key, readable only by the current user.
+pub fn create_keyfile(path: &Path) -> Result<(), String> {
+    let key: String = random_bytes(32)?
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect();
+
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)
+        .map_err(|e| format!("Failed to create keyfile '{}': {}", path.display(), e))?;
+    writeln!(file, "{key}")
+        .map_err(|e| format!("Failed to write keyfile '{}': {}", path.display(), e))
+}
+
+pub fn read_encrypted_file(path: &Path, config_dir: &Path) -> Result<String, String> {
+    let data = fs::read(path).map_err(|e| format!("Failed to read '{}': {}", path.display(), e))?;
+    let secret = load_secret(config_dir)?;
+    decrypt(&data, &secret).map_err(|e| format!("Failed to decrypt '{}': {}", path.display(), e))
+}
+
+/// Encrypts the plain TOML file `input` into `output`, creating a keyfile
+/// first if there is no key yet. Returns the new keyfile's path, if any.
+pub fn encrypt_file(
+    input: &Path,
+    output: &Path,
+    config_dir: &Path,
+) -> Result<Option<PathBuf>, String> {
+    let plaintext = fs::read_to_string(input)
+        .map_err(|e| format!("Failed to read '{}': {}", input.display(), e))?;
+    toml::from_str::<toml::Table>(&plaintext)
+        .map_err(|e| format!("'{}' is not valid TOML: {}", input.display(), e))?;
+
+    let keyfile = keyfile_path(config_dir);
+    let created = if env::var_os(PASSPHRASE_VAR).is_none() && !keyfile.exists() {
+        fs::create_dir_all(config_dir)
+            .map_err(|e| format!("Failed to create '{}': {}", config_dir.display(), e))?;
+        create_keyfile(&keyfile)?;
+        Some(keyfile)
+    } else {
+        None
+    };
+
+    let data = encrypt(&plaintext, &load_secret(config_dir)?)?;
+    if let Some(dir) = output.parent() {
+        fs::create_dir_all(dir)
+            .map_err(|e| format!("Failed to create '{}': {}", dir.display(), e))?;
+    }
+    fs::write(output, data)
+        .map_err(|e| format!("Failed to write '{}': {}", output.display(), e))?;
+    Ok(created)
+}
+
+pub fn encrypt(plaintext: &str, secret: &[u8]) -> Result<Vec<u8>, String> {
+    let salt = random_bytes(SALT_LEN)?;
+    let nonce = random_bytes(NONCE_LEN)?;
+    let ciphertext = cipher(secret, &salt)?
+        .encrypt(Nonce::from_slice(&nonce), plaintext.as_bytes())
+        .map_err(|_| "encryption failed".to_string())?;
+
+    Ok([MAGIC, &salt, &nonce, &ciphertext].concat())
+}
+
+pub fn decrypt(data: &[u8], secret: &[u8]) -> Result<String, String> {
+    let rest = data
+        .strip_prefix(MAGIC)
+        .filter(|rest| rest.len() > SALT_LEN + NONCE_LEN)
+        .ok_or("not a shire encrypted file")?;
+    let (salt, rest) = rest.split_at(SALT_LEN);
+    let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+
+    let plaintext = cipher(secret, salt)?
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| "wrong key or corrupted file")?;
+    String::from_utf8(plaintext).map_err(|_| "contents are not valid UTF-8".to_string())
+}
+
+fn cipher(secret: &[u8], salt: &[u8]) -> Result<ChaCha20Poly1305, String> {
+    let mut key = [0u8; 32];
+    Argon2::default()
+        .hash_password_into(secret, salt, &mut key)
+        .map_err(|e| format!("key derivation failed: {e}"))?;
+    Ok(ChaCha20Poly1305::new(Key::from_slice(&key)))
+}
+
+fn random_bytes(len: usize) -> Result<Vec<u8>, String> {
+    let mut bytes = vec![0u8; len];
+    fs::File::open("/dev/urandom")
+        .and_then(|mut urandom| urandom.read_exact(&mut bytes))
+        .map_err(|e| format!("Failed to read random bytes: {e}"))?;
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encrypt_round_trip() {
+        let data = encrypt("[[blocks]]\nname = \"secret\"\n", b"hunter2").unwrap();
+        assert!(!String::from_utf8_lossy(&data).contains("secret"));
+        assert_eq!(
+            decrypt(&data, b"hunter2").unwrap(),
+            "[[blocks]]\nname = \"secret\"\n"
+        );
+        assert_eq!(
+            decrypt(&data, b"hunter3").unwrap_err(),
+            "wrong key or corrupted file"
+        );
+        assert_eq!(
+            decrypt(b"plain text", b"hunter2").unwrap_err(),
+            "not a shire encrypted file"
+        );
+    }
+}
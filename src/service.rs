@@ -1,12 +1,12 @@
 use serde::Serialize;
 use std::env;
 use std::io;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 use std::time::Duration;
 use std::{fs, io::Error, thread};
 
-pub fn install_ctl(ctl: &launchctl::Service) -> Result<(), Error> {
+pub fn install_ctl(ctl: &launchctl::Service, config_path: &Path) -> Result<(), Error> {
     let exe_path = env::current_exe()?;
 
     let plist = format!(
@@ -20,6 +20,8 @@ pub fn install_ctl(ctl: &launchctl::Service) -> Result<(), Error> {
     <array>
         <string>{}</string>
         <string>daemon</string>
+        <string>--config</string>
+        <string>{}</string>
     </array>
     <key>RunAtLoad</key>
     <true/>
@@ -45,6 +47,7 @@ pub fn install_ctl(ctl: &launchctl::Service) -> Result<(), Error> {
             std::io::ErrorKind::InvalidData,
             "shire path is not valid UTF-8"
         ))?,
+        utf8_path(config_path)?,
     );
 
     fs::write(&ctl.plist_path, plist)?;
@@ -61,6 +64,17 @@ pub fn start() -> Result<(), Error> {
         )
     })?;
 
+    // Catch config mistakes here, where they can be shown, instead of in the
+    // daemon's log after it fails to start. The daemon is given the same path,
+    // since the service manager may not see this shell's XDG variables.
+    let config_path =
+        crate::config::default_config_path().map_err(|e| Error::other(e.to_string()))?;
+    let warnings = crate::validate::validate_file(&config_path)
+        .map_err(|report| Error::other(format!("Invalid configuration:\n{report}")))?;
+    for warning in warnings {
+        eprintln!("{warning}");
+    }
+
     install_manifest()?;
     if cfg!(target_os = "macos") {
         // Create the proper plist path in the user's LaunchAgents directory
@@ -73,7 +87,7 @@ pub fn start() -> Result<(), Error> {
             .plist_path(plist_path.to_str().unwrap())
             .build();
 
-        install_ctl(&ctl)?;
+        install_ctl(&ctl, &config_path)?;
         ctl.start()?;
     } else {
         let svc = SystemdService {
@@ -83,7 +97,7 @@ pub fn start() -> Result<(), Error> {
                 .join(".config/systemd/user/shire.service"),
         };
 
-        install_systemd(&svc)?;
+        install_systemd(&svc, &config_path)?;
 
         // Automate the Linux systemd commands that users previously had to run manually
         run_systemd_commands()?;
@@ -95,6 +109,15 @@ pub fn start() -> Result<(), Error> {
     Ok(())
 }
 
+fn utf8_path(path: &Path) -> Result<&str, Error> {
+    path.to_str().ok_or_else(|| {
+        Error::new(
+            io::ErrorKind::InvalidData,
+            format!("'{}' is not valid UTF-8", path.display()),
+        )
+    })
+}
+
 #[derive(Serialize)]
 struct Manifest<'a> {
     name: &'a str,
@@ -153,7 +176,7 @@ pub struct SystemdService {
     pub service_path: PathBuf, // full path to service file
 }
 
-pub fn install_systemd(service: &SystemdService) -> Result<(), Error> {
+pub fn install_systemd(service: &SystemdService, config_path: &Path) -> Result<(), Error> {
     let exe_path = env::current_exe()?;
 
     // Create the systemd service unit text
@@ -164,7 +187,7 @@ Description=Shire Daemon
 After=network.target
 
 [Service]
-ExecStart={} daemon
+ExecStart={} daemon --config {}
 Restart=always
 RestartSec=3
 Nice=-20
@@ -178,6 +201,7 @@ WantedBy=default.target
             io::ErrorKind::InvalidData,
             "shire path is not valid UTF-8"
         ))?,
+        utf8_path(config_path)?,
     );
 
     // Ensure parent directory exists
@@ -195,63 +219,39 @@ fn run_systemd_commands() -> Result<(), Error> {
     let output = Command::new("systemctl")
         .args(["--user", "daemon-reload"])
         .output()
-        .map_err(|e| {
-            io::Error::new(
-                io::ErrorKind::Other,
-                format!("Failed to run systemctl daemon-reload: {}", e),
-            )
-        })?;
+        .map_err(|e| io::Error::other(format!("Failed to run systemctl daemon-reload: {}", e)))?;
 
     if !output.status.success() {
-        return Err(io::Error::new(
-            io::ErrorKind::Other,
-            format!(
-                "systemctl daemon-reload failed: {}",
-                String::from_utf8_lossy(&output.stderr)
-            ),
-        ));
+        return Err(io::Error::other(format!(
+            "systemctl daemon-reload failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        )));
     }
 
     // Run systemctl --user enable shire.service
     let output = Command::new("systemctl")
         .args(["--user", "enable", "shire.service"])
         .output()
-        .map_err(|e| {
-            io::Error::new(
-                io::ErrorKind::Other,
-                format!("Failed to run systemctl enable: {}", e),
-            )
-        })?;
+        .map_err(|e| io::Error::other(format!("Failed to run systemctl enable: {}", e)))?;
 
     if !output.status.success() {
-        return Err(io::Error::new(
-            io::ErrorKind::Other,
-            format!(
-                "systemctl enable failed: {}",
-                String::from_utf8_lossy(&output.stderr)
-            ),
-        ));
+        return Err(io::Error::other(format!(
+            "systemctl enable failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        )));
     }
 
     // Run systemctl --user start shire.service
     let output = Command::new("systemctl")
         .args(["--user", "start", "shire.service"])
         .output()
-        .map_err(|e| {
-            io::Error::new(
-                io::ErrorKind::Other,
-                format!("Failed to run systemctl start: {}", e),
-            )
-        })?;
+        .map_err(|e| io::Error::other(format!("Failed to run systemctl start: {}", e)))?;
 
     if !output.status.success() {
-        return Err(io::Error::new(
-            io::ErrorKind::Other,
-            format!(
-                "systemctl start failed: {}",
-                String::from_utf8_lossy(&output.stderr)
-            ),
-        ));
+        return Err(io::Error::other(format!(
+            "systemctl start failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        )));
     }
 
     Ok(())
@@ -293,12 +293,8 @@ fn stop_macos() -> Result<(), Error> {
             .build();
 
         // Stop the service (ignore errors if it's not running)
-        ctl.stop().map_err(|e| {
-            std::io::Error::new(
-                std::io::ErrorKind::Other,
-                format!("Failed to stop launchd service: {}", e),
-            )
-        })?;
+        ctl.stop()
+            .map_err(|e| std::io::Error::other(format!("Failed to stop launchd service: {}", e)))?;
     }
 
     Ok(())
@@ -322,10 +318,7 @@ fn uninstall_macos() -> Result<(), Error> {
 
     if plist_path.exists() {
         fs::remove_file(&plist_path).map_err(|e| {
-            std::io::Error::new(
-                std::io::ErrorKind::Other,
-                format!("Failed to remove launchd plist file: {}", e),
-            )
+            std::io::Error::other(format!("Failed to remove launchd plist file: {}", e))
         })?;
     }
 
@@ -350,16 +343,13 @@ fn uninstall_linux() -> Result<(), Error> {
 
     if service_path.exists() {
         fs::remove_file(&service_path).map_err(|e| {
-            std::io::Error::new(
-                std::io::ErrorKind::Other,
-                format!("Failed to remove systemd service file: {}", e),
-            )
+            std::io::Error::other(format!("Failed to remove systemd service file: {}", e))
         })?;
     }
 
     // Reload systemd daemon to reflect changes
     let _ = Command::new("systemctl")
-        .args(&["--user", "daemon-reload"])
+        .args(["--user", "daemon-reload"])
         .output();
 
     // Remove Mozilla native messaging manifest
@@ -371,14 +361,9 @@ fn uninstall_linux() -> Result<(), Error> {
 fn stop_linux() -> Result<(), Error> {
     // Stop the service
     let output = Command::new("systemctl")
-        .args(&["--user", "stop", "shire.service"])
+        .args(["--user", "stop", "shire.service"])
         .output()
-        .map_err(|e| {
-            io::Error::new(
-                io::ErrorKind::Other,
-                format!("Failed to run systemctl stop: {}", e),
-            )
-        })?;
+        .map_err(|e| io::Error::other(format!("Failed to run systemctl stop: {}", e)))?;
 
     // Don't error if the service wasn't running
     if !output.status.success() {
@@ -390,14 +375,9 @@ fn stop_linux() -> Result<(), Error> {
 
     // Disable the service
     let output = Command::new("systemctl")
-        .args(&["--user", "disable", "shire.service"])
+        .args(["--user", "disable", "shire.service"])
         .output()
-        .map_err(|e| {
-            io::Error::new(
-                io::ErrorKind::Other,
-                format!("Failed to run systemctl disable: {}", e),
-            )
-        })?;
+        .map_err(|e| io::Error::other(format!("Failed to run systemctl disable: {}", e)))?;
 
     // Don't error if the service wasn't enabled
     if !output.status.success() {
@@ -416,19 +396,13 @@ fn remove_mozilla_manifest() -> Result<(), Error> {
 
     if manifest_path.exists() {
         fs::remove_file(&manifest_path).map_err(|e| {
-            std::io::Error::new(
-                std::io::ErrorKind::Other,
-                format!("Failed to remove Mozilla manifest file: {}", e),
-            )
+            std::io::Error::other(format!("Failed to remove Mozilla manifest file: {}", e))
         })?;
     }
 
     Ok(())
 }
 
-// TODO: Eventually I think I should move the verification for the configuration
-// file into the cli, that way when trying to start, I can get detailed error 
-// messages without needing to log to the file.
 fn verify_daemon_started() -> Result<(), Error> {
     use std::os::unix::net::UnixStream;
     
@@ -439,14 +413,12 @@ fn verify_daemon_started() -> Result<(), Error> {
     match UnixStream::connect("/tmp/shire_cli.sock") {
         Ok(_) => Ok(()),
         Err(_) => {
-            let error_message = format!(
-                "Failed to start shire daemon. Socket connection failed.\n\n\
+            let error_message = "Failed to start shire daemon. Socket connection failed.\n\n\
                 Please check the error logs for more details:\n\
                 - Error log: /tmp/shire.stderr.log\n\
-                - Output log: /tmp/shire.stdout.log"
-            );
+                - Output log: /tmp/shire.stdout.log";
             
-            Err(Error::new(io::ErrorKind::Other, error_message))
+            Err(Error::other(error_message))
         }
     }
 }
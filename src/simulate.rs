@@ -0,0 +1,199 @@
+use crate::config::Config;
+use crate::state;
+use chrono::{DateTime, Duration, Local};
+use std::cmp::Ordering;
+
+/// Longer ranges would run past the events loaded from calendar files.
+const MAX_SIMULATION_DAYS: i64 = 366;
+
+/// A block's schedule switching it on or off.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Transition {
+    pub at: DateTime<Local>,
+    pub block: String,
+    pub active: bool,
+}
+
+/// Runs the config's schedules from `from` to `to` through the same
+/// engine as the daemon, and returns every moment a block's schedule
+/// switches it on or off. Blocks that are scheduled at `from` get an "on"
+/// transition at `from`. Manual starts, sessions and `shire schedule`
+/// changes aren't part of the simulation.
+pub fn simulate(
+    config: &Config,
+    from: DateTime<Local>,
+    to: DateTime<Local>,
+) -> Result<Vec<Transition>, String> {
+    if to <= from {
+        return Err("`--to` must be after `--from`".to_string());
+    }
+    if to - from > Duration::days(MAX_SIMULATION_DAYS) {
+        return Err(format!("Simulations can cover at most {MAX_SIMULATION_DAYS} days"));
+    }
+
+    let schedule = state::build_weekly_schedule(&config.schedule, &config.exceptions, from);
+    let mut blocks: Vec<(&str, bool)> = config
+        .blocks
+        .iter()
+        .map(|block| (block.name.as_str(), false))
+        .collect();
+    blocks.sort();
+
+    let mut transitions = Vec::new();
+    let mut record = |at: DateTime<Local>, blocks: &mut Vec<(&str, bool)>| {
+        for (block, active) in blocks.iter_mut() {
+            let scheduled = state::is_block_currently_scheduled(block, at, &schedule);
+            if scheduled != *active {
+                *active = scheduled;
+                transitions.push(Transition {
+                    at,
+                    block: block.to_string(),
+                    active: scheduled,
+                });
+            }
+        }
+    };
+
+    record(from, &mut blocks);
+    let mut now = from;
+    while let Some((at, _)) = state::next_scheduled_events(&schedule, now) {
+        if at >= to {
+            break;
+        }
+        // Every event due at `at` has fired, so back-to-back windows and
+        // overlaps don't show up as a flicker
+        record(at, &mut blocks);
+        now = at;
+    }
+    Ok(transitions)
+}
+
+/// A line of a diff between two simulations.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Difference<'a> {
+    /// Only in the first simulation.
+    Removed(&'a Transition),
+    /// Only in the second.
+    Added(&'a Transition),
+}
+
+/// The transitions that differ between two simulations of the same range,
+/// in time order.
+pub fn diff<'a>(before: &'a [Transition], after: &'a [Transition]) -> Vec<Difference<'a>> {
+    let key = |transition: &Transition| (transition.at, transition.block.clone(), transition.active);
+    let (mut before, mut after) = (before.iter().peekable(), after.iter().peekable());
+    let mut differences = Vec::new();
+    loop {
+        let order = match (before.peek(), after.peek()) {
+            (None, None) => break,
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (Some(old), Some(new)) => key(old).cmp(&key(new)),
+        };
+        match order {
+            Ordering::Less => differences.extend(before.next().map(Difference::Removed)),
+            Ordering::Greater => differences.extend(after.next().map(Difference::Added)),
+            Ordering::Equal => {
+                before.next();
+                after.next();
+            }
+        }
+    }
+    differences
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::parse_config_str;
+    use chrono::{TimeZone, Utc};
+    use std::path::Path;
+
+    fn utc(month: u32, day: u32, hour: u32) -> DateTime<Local> {
+        Utc.with_ymd_and_hms(2026, month, day, hour, 0, 0).unwrap().with_timezone(&Local)
+    }
+
+    fn config(extra: &str) -> Config {
+        let contents = format!(
+            r#"
+            [[blocks]]
+            name = "games"
+
+            [[blocks]]
+            name = "news"
+
+            [[schedule]]
+            name = "nights"
+            block = "games"
+            days = ["Sat"]
+            start = "22:00"
+            end = "6:00"
+            timezone = "Europe/Berlin"
+
+            [[schedule]]
+            block = "news"
+            days = ["Sun"]
+            start = "0:00"
+            end = "1:00"
+            timezone = "Europe/Berlin"
+            {extra}
+            "#
+        );
+        parse_config_str(&contents, Path::new(".")).unwrap()
+    }
+
+    #[test]
+    fn test_simulation_follows_overnight_windows_dst_and_exceptions() {
+        let config = config(
+            r#"
+            [[exceptions]]
+            date = "2026-10-31"
+            schedules = ["nights"]
+            "#,
+        );
+        // Berlin leaves summer time early on Sunday, October 25
+        let transitions = simulate(&config, utc(10, 24, 22), utc(11, 8, 12)).unwrap();
+        let expected = [
+            (utc(10, 24, 22), "games", true),
+            (utc(10, 24, 22), "news", true),
+            (utc(10, 24, 23), "news", false),
+            (utc(10, 25, 5), "games", false),
+            (utc(10, 31, 23), "news", true),
+            (utc(11, 1, 0), "news", false),
+            (utc(11, 7, 21), "games", true),
+            (utc(11, 7, 23), "news", true),
+            (utc(11, 8, 0), "news", false),
+            (utc(11, 8, 5), "games", false),
+        ];
+        let found: Vec<_> = transitions
+            .iter()
+            .map(|t| (t.at, t.block.as_str(), t.active))
+            .collect();
+        assert_eq!(found, expected);
+
+        assert!(simulate(&config, utc(11, 8, 12), utc(10, 24, 22)).is_err());
+    }
+
+    #[test]
+    fn test_diff_shows_changed_transitions() {
+        let (from, to) = (utc(10, 30, 0), utc(11, 2, 0));
+        let before = simulate(&config(""), from, to).unwrap();
+        let after = simulate(
+            &config(
+                r#"
+                [[exceptions]]
+                date = "2026-10-31"
+                blocks = ["games"]
+                "#,
+            ),
+            from,
+            to,
+        )
+        .unwrap();
+
+        let differences = diff(&before, &after);
+        assert_eq!(differences.len(), 2);
+        assert!(differences.iter().all(|d| matches!(d, Difference::Removed(t) if t.block == "games")));
+        assert!(diff(&before, &before).is_empty());
+    }
+}
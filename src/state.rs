@@ -1,7 +1,13 @@
-use crate::config::Config;
-use chrono::{Datelike, NaiveTime, Weekday};
-use serde::Serialize;
+use crate::apps::{self, ApplicationAction};
+use crate::calendar;
+use crate::config::{self, Config};
+use crate::timespec;
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDate, NaiveTime, TimeZone, Weekday};
+use chrono_tz::Tz;
+use croner::{Cron, Direction};
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::sync::Mutex;
 
@@ -28,43 +34,407 @@ impl PartialOrd for OrderableWeekday {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct ApplicationState {
     pub blocks: HashMap<String, Block>,
     pub schedule: Vec<Event>,
+    pub pomodoro: Option<Pomodoro>,
+    pub sessions: HashMap<String, SessionTemplate>,
+    pub active_session: Option<ActiveSession>,
+    /// Block starts booked for later, in the order they were added.
+    pub one_offs: Vec<OneOff>,
+    /// The config file's schedules and exceptions, kept to rebuild
+    /// `schedule` when the overlay changes.
+    pub config_schedules: Vec<config::Schedule>,
+    pub exceptions: Vec<config::Exception>,
+    /// The config's `timezone`, also used for schedules added at runtime.
+    pub default_timezone: Option<String>,
+    pub schedule_overlay: ScheduleOverlay,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+/// Schedule changes made with `shire schedule add` and `pause`, kept in the
+/// state directory and applied on top of the config file's schedules.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ScheduleOverlay {
+    #[serde(default)]
+    pub added: Vec<AddedSchedule>,
+    #[serde(default)]
+    pub pauses: Vec<SchedulePause>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AddedSchedule {
+    pub id: u32,
+    #[serde(flatten)]
+    pub schedule: config::Schedule,
+}
+
+/// Scheduled starts of `block` are skipped until `until`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SchedulePause {
+    pub block: String,
+    pub until: DateTime<Local>,
+}
+
+/// A block start booked for a later time with `shire block start --at`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OneOff {
+    pub id: u32,
+    pub block: String,
+    pub at: DateTime<Local>,
+    pub until: Option<DateTime<Local>>,
+    pub lock_until: Option<DateTime<Local>>,
+}
+
+/// Time counted against a block's daily allowance, kept in the state
+/// directory so a restart doesn't hand out a fresh allowance.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AllowanceUsage {
+    pub date: NaiveDate,
+    /// Seconds used on `date`.
+    pub used: i64,
+}
+
+/// A focus session as defined in the config.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SessionTemplate {
+    pub blocks: Vec<String>,
+    pub duration: Duration,
+    pub lock: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ActiveSession {
+    pub name: String,
+    pub blocks: Vec<String>,
+    pub ends_at: DateTime<Local>,
+    pub locked: bool,
+}
+
+/// A running work/break cycle over a set of blocks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pomodoro {
+    pub blocks: Vec<String>,
+    pub work: Duration,
+    pub break_duration: Duration,
+    pub cycles: u32,
+    /// The current cycle, starting at 1.
+    pub cycle: u32,
+    pub phase: PomodoroPhase,
+    pub phase_ends_at: DateTime<Local>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum PomodoroPhase {
+    Work,
+    Break,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
 pub struct Block {
     pub whitelist: Option<Vec<String>>,
     pub blacklist: Option<Vec<String>>,
     pub block_state: BlockState,
+    /// The layers holding the block active, strongest first. Empty when it
+    /// is unblocked.
+    pub active_because: Vec<Layer>,
+    /// Started by hand with no end time, or `active_by_default`.
+    #[serde(skip)]
+    pub manual: bool,
+    /// One of the block's scheduled windows is open and not paused.
+    #[serde(skip)]
+    pub scheduled: bool,
+    /// Stopped by hand during an open window, which releases the block from
+    /// the schedule until that window ends.
+    #[serde(skip)]
+    pub schedule_overridden: bool,
+    /// When set, the block is held active until this time.
+    #[serde(skip)]
+    pub unblock_at: Option<DateTime<Local>>,
+    /// While in the future, the block cannot be stopped.
+    #[serde(skip)]
+    pub locked_until: Option<DateTime<Local>>,
+    /// How long a manual stop waits before it takes effect.
+    #[serde(skip)]
+    pub unblock_delay: Option<Duration>,
+    /// A requested stop that takes effect at this time unless cancelled.
+    #[serde(skip)]
+    pub pending_unblock_at: Option<DateTime<Local>>,
+    /// How long matching sites may be used per day before the block kicks in.
+    #[serde(skip)]
+    pub daily_allowance: Option<Duration>,
+    /// Time spent on matching sites on `usage_date`.
+    #[serde(skip)]
+    pub usage: Duration,
+    #[serde(skip)]
+    pub usage_date: Option<NaiveDate>,
+    /// Set when the block was activated because the allowance ran out.
+    #[serde(skip)]
+    pub allowance_exhausted: bool,
+    /// Executable names or command lines that may not run while the block
+    /// is active.
+    #[serde(skip)]
+    pub applications: Vec<String>,
+    #[serde(skip)]
+    pub application_action: ApplicationAction,
+    /// The latest signals sent to the block's applications, oldest first.
+    #[serde(skip)]
+    pub application_log: Vec<apps::Action>,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+impl Block {
+    pub fn is_locked(&self, now: DateTime<Local>) -> bool {
+        self.locked_until.is_some_and(|until| until > now)
+    }
+
+    /// Keeps the last few application actions for `shire block list`.
+    pub fn log_application_action(&mut self, action: apps::Action) {
+        if self.application_log.len() == APPLICATION_LOG_LENGTH {
+            self.application_log.remove(0);
+        }
+        self.application_log.push(action);
+    }
+
+    /// Time left of today's allowance, if the block has one.
+    pub fn allowance_left(&self) -> Option<Duration> {
+        self.daily_allowance
+            .map(|allowance| (allowance - self.usage).max(Duration::zero()))
+    }
+
+    /// Mirrors the extension's matching: blacklist entries are URL prefixes
+    /// and whitelist entries are prefixes with an optional trailing `*`.
+    pub fn matches_url(&self, url: &str) -> bool {
+        let url = url.trim_start_matches("https://").trim_start_matches("http://");
+        let url = url.strip_prefix("www.").unwrap_or(url);
+
+        let blacklisted = self
+            .blacklist
+            .iter()
+            .flatten()
+            .any(|pattern| url.starts_with(pattern.as_str()));
+        let whitelisted = self.whitelist.iter().flatten().any(|pattern| {
+            url.starts_with(pattern.strip_suffix('*').unwrap_or(pattern))
+        });
+
+        blacklisted && !whitelisted
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize)]
 pub enum BlockState {
+    #[default]
     Unblocked,
     Blocked,
     // BlockedWithLock,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+/// Something that can hold a block active, strongest first. Each layer is
+/// tracked on its own and a block is active while any of them holds it, so
+/// one letting go (a window closing, a timer running out) never undoes
+/// another. Where they conflict:
+///
+/// - a lock or a used-up allowance can't be stopped at all;
+/// - a session or pomodoro holds its blocks until it is stopped itself;
+/// - a manual stop releases the timer and manual layers, and overrides the
+///   schedule until the open window ends.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Layer {
+    Lock,
+    Allowance,
+    Session,
+    Timer,
+    Manual,
+    Schedule,
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct Event {
     pub block: String,
-    pub day: OrderableWeekday,
-    pub time: NaiveTime,
+    pub recurrence: Recurrence,
     pub action: ScheduleAction,
+    pub dates: DateFilter,
+    /// The zone the recurrence is in, or None for the system's local time.
+    pub timezone: Option<Tz>,
+    /// Shared by the start and end of the same window.
+    pub window: usize,
+}
+
+/// When an event repeats.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Recurrence {
+    /// Every week on `day` at `time`. `overnight_end` is set on the end of a
+    /// window that runs past midnight, which belongs to the window started
+    /// the day before.
+    Weekly {
+        day: OrderableWeekday,
+        time: NaiveTime,
+        overnight_end: bool,
+    },
+    /// Windows start at each match of `cron` and end `duration` later.
+    Cron { cron: Arc<Cron>, duration: Duration },
+    /// The windows of a calendar file's events, already expanded.
+    Calendar { windows: Arc<Vec<calendar::Window>> },
+}
+
+impl Event {
+    /// The moments this event fires on `date` in its own time zone, each
+    /// with the date its window started. Windows that start on a date the
+    /// schedule doesn't allow are skipped entirely.
+    fn fire_times(&self, date: NaiveDate) -> Vec<(DateTime<Local>, NaiveDate)> {
+        match &self.recurrence {
+            Recurrence::Weekly {
+                day,
+                time,
+                overnight_end,
+            } => {
+                let window_date = if *overnight_end {
+                    date - Duration::days(1)
+                } else {
+                    date
+                };
+                if day.0 != date.weekday() || !self.dates.allows(window_date) {
+                    return Vec::new();
+                }
+                self.at(date, *time).map(|at| (at, window_date)).into_iter().collect()
+            }
+            Recurrence::Cron { cron, duration } => {
+                // Windows are at most a day long, so an end belongs to a
+                // window that started the same day or the day before
+                let window_dates = match self.action {
+                    ScheduleAction::StartBlock => vec![date],
+                    ScheduleAction::EndBlock => vec![date - Duration::days(1), date],
+                };
+                let offset = match self.action {
+                    ScheduleAction::StartBlock => Duration::zero(),
+                    ScheduleAction::EndBlock => *duration,
+                };
+                window_dates
+                    .into_iter()
+                    .filter(|window_date| self.dates.allows(*window_date))
+                    .flat_map(|window_date| {
+                        self.cron_matches(cron, window_date)
+                            .into_iter()
+                            .map(move |start| (start + offset, window_date))
+                    })
+                    .filter(|(at, _)| self.zone_date(*at) == date)
+                    .collect()
+            }
+            Recurrence::Calendar { windows } => windows
+                .iter()
+                .map(|(start, end)| match self.action {
+                    ScheduleAction::StartBlock => (*start, start.date_naive()),
+                    ScheduleAction::EndBlock => (*end, start.date_naive()),
+                })
+                .filter(|(at, window_date)| {
+                    at.date_naive() == date && self.dates.allows(*window_date)
+                })
+                .collect(),
+        }
+    }
+
+    /// When the window this start event opened at `start`, on `window_date`,
+    /// closes.
+    fn window_end(
+        &self,
+        schedule: &[Event],
+        start: DateTime<Local>,
+        window_date: NaiveDate,
+    ) -> Option<DateTime<Local>> {
+        match &self.recurrence {
+            Recurrence::Cron { duration, .. } => return Some(start + *duration),
+            Recurrence::Calendar { windows } => {
+                return windows
+                    .iter()
+                    .filter(|(window_start, _)| *window_start == start)
+                    .map(|(_, end)| *end)
+                    .max();
+            }
+            Recurrence::Weekly { .. } => {}
+        }
+        let end = schedule
+            .iter()
+            .find(|end| end.window == self.window && end.action == ScheduleAction::EndBlock)?;
+        let Recurrence::Weekly {
+            time,
+            overnight_end,
+            ..
+        } = end.recurrence
+        else {
+            return None;
+        };
+        let days = if overnight_end { 1 } else { 0 };
+        end.at(window_date + Duration::days(days), time)
+    }
+
+    /// The moment `time` on `date` happens in this event's time zone.
+    fn at(&self, date: NaiveDate, time: NaiveTime) -> Option<DateTime<Local>> {
+        let datetime = date.and_time(time);
+        match &self.timezone {
+            Some(zone) => timespec::in_zone(zone, datetime).map(|at| at.with_timezone(&Local)),
+            None => timespec::to_local(datetime),
+        }
+    }
+
+    /// The date `at` falls on in this event's time zone.
+    fn zone_date(&self, at: DateTime<Local>) -> NaiveDate {
+        match &self.timezone {
+            Some(zone) => at.with_timezone(zone).date_naive(),
+            None => at.date_naive(),
+        }
+    }
+
+    fn cron_matches(&self, cron: &Cron, date: NaiveDate) -> Vec<DateTime<Local>> {
+        match &self.timezone {
+            Some(zone) => cron_matches_on(cron, zone, date),
+            None => cron_matches_on(cron, &Local, date),
+        }
+    }
+}
+
+/// The times `cron` matches during `date` in `zone`.
+fn cron_matches_on<Z: TimeZone + Copy>(cron: &Cron, zone: &Z, date: NaiveDate) -> Vec<DateTime<Local>> {
+    let Some(midnight) = timespec::in_zone(zone, date.and_time(NaiveTime::MIN)) else {
+        return Vec::new();
+    };
+    cron.iter_from(midnight, Direction::Forward)
+        .take_while(|at| at.date_naive() == date)
+        .map(|at| at.with_timezone(&Local))
+        .collect()
+}
+
+/// The dates a schedule's windows may start on: within its `valid_from` and
+/// `valid_until`, and outside any exceptions that suspend it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DateFilter {
+    pub valid_from: Option<NaiveDate>,
+    pub valid_until: Option<NaiveDate>,
+    pub suspended: Vec<(NaiveDate, NaiveDate)>,
+}
+
+impl DateFilter {
+    pub fn allows(&self, date: NaiveDate) -> bool {
+        self.valid_from.is_none_or(|from| date >= from)
+            && self.valid_until.is_none_or(|until| date <= until)
+            && !self
+                .suspended
+                .iter()
+                .any(|(first, last)| (*first..=*last).contains(&date))
+    }
 }
 
-const DAY_MAP: &[(&str, Weekday)] = &[
-    ("Mon", Weekday::Mon),
-    ("Tue", Weekday::Tue),
-    ("Wed", Weekday::Wed),
-    ("Thu", Weekday::Thu),
-    ("Fri", Weekday::Fri),
-    ("Sat", Weekday::Sat),
-    ("Sun", Weekday::Sun),
-];
+/// How many days to look ahead or back for scheduled events. Exceptions and
+/// validity ranges can leave gaps much longer than a week.
+const SCHEDULE_HORIZON_DAYS: i64 = 400;
+
+/// How many application actions each block remembers.
+const APPLICATION_LOG_LENGTH: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopOutcome {
+    Stopped,
+    Pending(DateTime<Local>),
+}
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub enum ScheduleAction {
@@ -72,190 +442,1651 @@ pub enum ScheduleAction {
     EndBlock,
 }
 
-pub fn initialize_application_state(config: Config) -> Arc<Mutex<ApplicationState>> {
-    let application_state = Arc::new(Mutex::new(ApplicationState {
-        blocks: HashMap::new(),
-        schedule: Vec::new(),
-    }));
+pub fn initialize_application_state(
+    config: Config,
+    schedule_overlay: ScheduleOverlay,
+    now: DateTime<Local>,
+) -> Arc<Mutex<ApplicationState>> {
+    let application_state = Arc::new(Mutex::new(ApplicationState::default()));
 
     // Block initialization
     config.blocks.iter().for_each(|block| {
-        let state = if matches!(block.active_by_default, Some(true)) {
-            BlockState::Blocked
-        } else {
-            BlockState::Unblocked
-        };
-
         application_state.lock().unwrap().blocks.insert(
             block.name.clone(),
             Block {
                 whitelist: block.whitelist.clone(),
                 blacklist: block.blacklist.clone(),
-                block_state: state,
+                manual: block.active_by_default.unwrap_or(false),
+                // Durations are checked by config validation
+                unblock_delay: block
+                    .unblock_delay
+                    .as_deref()
+                    .and_then(|delay| timespec::parse_duration(delay).ok()),
+                daily_allowance: block
+                    .daily_allowance
+                    .as_deref()
+                    .and_then(|allowance| timespec::parse_duration(allowance).ok()),
+                usage_date: Some(now.date_naive()),
+                applications: block.applications.clone().unwrap_or_default(),
+                // Checked by config validation
+                application_action: block
+                    .application_action
+                    .as_deref()
+                    .and_then(|action| ApplicationAction::parse(action).ok())
+                    .unwrap_or_default(),
+                ..Default::default()
+            },
+        );
+    });
+
+    // Session initialization
+    config.sessions.iter().for_each(|session| {
+        application_state.lock().unwrap().sessions.insert(
+            session.name.clone(),
+            SessionTemplate {
+                blocks: session.blocks.clone(),
+                // Durations are checked by config validation
+                duration: timespec::parse_duration(&session.duration)
+                    .unwrap_or_else(|_| Duration::zero()),
+                lock: session.lock.unwrap_or(false),
             },
         );
     });
 
     // Schedule initialization
-    let schedules = config.schedule.clone();
+    {
+        let mut app_state = application_state.lock().unwrap();
+        app_state.config_schedules = config.schedule;
+        app_state.exceptions = config.exceptions;
+        app_state.default_timezone = config.timezone;
+        app_state.schedule_overlay = schedule_overlay;
+        app_state.schedule_overlay.pauses.retain(|pause| pause.until > now);
+        rebuild_schedule(&mut app_state, now);
+
+        // Activate blocks that should be currently active based on schedule
+        activate_scheduled_blocks(&mut app_state, now);
+        refresh_block_states(&mut app_state, now);
+    }
+
+    application_state
+}
+
+fn parse_time(time_str: &str) -> NaiveTime {
+    // Schedule times are checked by config validation before we get here
+    timespec::parse_time_of_day(time_str).expect("Invalid time")
+}
+
+/// Recomputes every block's state from its layers. Returns true if any
+/// block's state or the layers holding it changed.
+fn refresh_block_states(application_state: &mut ApplicationState, now: DateTime<Local>) -> bool {
+    let ApplicationState {
+        blocks,
+        active_session,
+        pomodoro,
+        ..
+    } = application_state;
+
+    let mut changed = false;
+    for (name, block) in blocks.iter_mut() {
+        let in_session = active_session
+            .as_ref()
+            .is_some_and(|session| session.ends_at > now && session.blocks.contains(name))
+            || pomodoro.as_ref().is_some_and(|pomodoro| {
+                pomodoro.phase == PomodoroPhase::Work && pomodoro.blocks.contains(name)
+            });
+        let layers = [
+            (Layer::Lock, block.is_locked(now)),
+            (Layer::Allowance, block.allowance_exhausted),
+            (Layer::Session, in_session),
+            (Layer::Timer, block.unblock_at.is_some_and(|at| at > now)),
+            (Layer::Manual, block.manual),
+            (Layer::Schedule, block.scheduled && !block.schedule_overridden),
+        ];
+        let active_because: Vec<Layer> = layers
+            .into_iter()
+            .filter_map(|(layer, holds)| holds.then_some(layer))
+            .collect();
+
+        if active_because != block.active_because {
+            block.block_state = if active_because.is_empty() {
+                BlockState::Unblocked
+            } else {
+                BlockState::Blocked
+            };
+            block.active_because = active_because;
+            changed = true;
+        }
+    }
+    changed
+}
+
+/// Starts a block by hand, holding it until `until` on the timer layer or
+/// indefinitely on the manual layer, and locking it until `lock_until`.
+/// Starting a block again replaces the earlier end time.
+pub fn start_block(
+    application_state: &mut ApplicationState,
+    block_name: &str,
+    until: Option<DateTime<Local>>,
+    lock_until: Option<DateTime<Local>>,
+    now: DateTime<Local>,
+) -> Result<(), String> {
+    let block = application_state
+        .blocks
+        .get_mut(block_name)
+        .ok_or_else(|| format!("Block '{block_name}' not found"))?;
+
+    block.manual = until.is_none();
+    block.unblock_at = until;
+    block.pending_unblock_at = None;
+    if let Some(lock_until) = lock_until {
+        block.locked_until = block.locked_until.max(Some(lock_until));
+    }
+
+    refresh_block_states(application_state, now);
+    Ok(())
+}
+
+/// Locks a block until `lock_until`, holding it active until then on top of
+/// whatever else holds it.
+pub fn lock_block(
+    application_state: &mut ApplicationState,
+    block_name: &str,
+    lock_until: DateTime<Local>,
+    now: DateTime<Local>,
+) -> Result<(), String> {
+    let block = application_state
+        .blocks
+        .get_mut(block_name)
+        .ok_or_else(|| format!("Block '{block_name}' not found"))?;
+
+    block.locked_until = block.locked_until.max(Some(lock_until));
+    block.pending_unblock_at = None;
+
+    refresh_block_states(application_state, now);
+    Ok(())
+}
+
+/// Checks that nothing stronger than a manual stop is holding the block.
+fn check_stoppable<'a>(
+    application_state: &'a ApplicationState,
+    block_name: &str,
+    now: DateTime<Local>,
+) -> Result<&'a Block, String> {
+    let block = application_state
+        .blocks
+        .get(block_name)
+        .ok_or_else(|| format!("Block '{block_name}' not found"))?;
+
+    if let Some(until) = block.locked_until.filter(|until| *until > now) {
+        return Err(format!(
+            "Block '{block_name}' is locked until {}",
+            until.format("%Y-%m-%d %H:%M")
+        ));
+    }
+    if block.allowance_exhausted {
+        return Err(format!(
+            "Block '{block_name}' has used up its daily allowance and unblocks at midnight"
+        ));
+    }
+    if let Some(session) = &application_state.active_session
+        && session.blocks.iter().any(|name| name == block_name)
+    {
+        return Err(format!(
+            "Block '{block_name}' is part of session '{}'; stop the session instead",
+            session.name
+        ));
+    }
+    if let Some(pomodoro) = &application_state.pomodoro
+        && pomodoro.blocks.iter().any(|name| name == block_name)
+    {
+        return Err(format!(
+            "Block '{block_name}' is part of the running pomodoro; stop the pomodoro instead"
+        ));
+    }
+
+    Ok(block)
+}
+
+/// Drops the layers a manual stop releases.
+fn release(block: &mut Block) {
+    block.manual = false;
+    block.unblock_at = None;
+    block.locked_until = None;
+    block.pending_unblock_at = None;
+    block.schedule_overridden = block.scheduled;
+}
+
+/// Handles a user's request to stop a block. Blocks with an unblock delay
+/// only record a pending unblock, which `expire_timers` applies once the
+/// delay has passed.
+pub fn request_stop(
+    application_state: &mut ApplicationState,
+    block_name: &str,
+    now: DateTime<Local>,
+) -> Result<StopOutcome, String> {
+    let block = check_stoppable(application_state, block_name, now)?;
+
+    match block.unblock_delay {
+        Some(delay) if block.block_state == BlockState::Blocked => {
+            let block = application_state.blocks.get_mut(block_name).unwrap();
+            let at = *block.pending_unblock_at.get_or_insert(now + delay);
+            Ok(StopOutcome::Pending(at))
+        }
+        _ => stop_block(application_state, block_name, now).map(|_| StopOutcome::Stopped),
+    }
+}
+
+/// Cancels a pending unblock so the block stays active.
+pub fn cancel_pending_unblock(
+    application_state: &mut ApplicationState,
+    block_name: &str,
+) -> Result<(), String> {
+    let block = application_state
+        .blocks
+        .get_mut(block_name)
+        .ok_or_else(|| format!("Block '{block_name}' not found"))?;
+
+    block
+        .pending_unblock_at
+        .take()
+        .map(|_| ())
+        .ok_or_else(|| format!("Block '{block_name}' has no pending unblock"))
+}
+
+/// Stops a block by hand right away, unless something stronger than a
+/// manual stop holds it. See [`Layer`] for what a stop releases.
+pub fn stop_block(
+    application_state: &mut ApplicationState,
+    block_name: &str,
+    now: DateTime<Local>,
+) -> Result<(), String> {
+    check_stoppable(application_state, block_name, now)?;
+    release(application_state.blocks.get_mut(block_name).unwrap());
+
+    refresh_block_states(application_state, now);
+    Ok(())
+}
+
+/// Lets go of timers, pending unblocks, locks and allowances whose time is
+/// up. Returns true if any block changed state or the layers holding it.
+pub fn expire_timers(application_state: &mut ApplicationState, now: DateTime<Local>) -> bool {
+    for (name, block) in application_state.blocks.iter_mut() {
+        if block.daily_allowance.is_some() && block.usage_date != Some(now.date_naive()) {
+            block.usage = Duration::zero();
+            block.usage_date = Some(now.date_naive());
+            if block.allowance_exhausted {
+                block.allowance_exhausted = false;
+                println!("Daily allowance reset: {name}");
+            }
+        }
+
+        if block.locked_until.is_some_and(|until| until <= now) {
+            block.locked_until = None;
+        }
+
+        if block.unblock_at.is_some_and(|at| at <= now) {
+            block.unblock_at = None;
+            println!("Timed block ended: {name}");
+        }
+
+        if block.pending_unblock_at.is_some_and(|at| at <= now) {
+            release(block);
+            println!("Pending unblock applied: {name}");
+        }
+    }
+
+    if application_state
+        .active_session
+        .as_ref()
+        .is_some_and(|session| session.ends_at <= now)
+    {
+        let session = application_state.active_session.take().unwrap();
+        println!("Session '{}' ended", session.name);
+    }
+
+    end_schedule_pauses(application_state, now);
+    advance_pomodoro(application_state, now);
+    refresh_block_states(application_state, now)
+}
+
+/// Books a block to start at `at`, ending at `until` and locked until
+/// `lock_until` if given. Returns the booking with its new id.
+pub fn add_one_off(
+    application_state: &mut ApplicationState,
+    block_name: &str,
+    at: DateTime<Local>,
+    until: Option<DateTime<Local>>,
+    lock_until: Option<DateTime<Local>>,
+    now: DateTime<Local>,
+) -> Result<OneOff, String> {
+    if !application_state.blocks.contains_key(block_name) {
+        return Err(format!("Block '{block_name}' not found"));
+    }
+    if at <= now {
+        return Err(format!(
+            "Start time {} is in the past",
+            at.format("%Y-%m-%d %H:%M")
+        ));
+    }
+    if until.is_some_and(|until| until <= at) {
+        return Err("The block must end after it starts".to_string());
+    }
+
+    let one_off = OneOff {
+        id: application_state.one_offs.iter().map(|o| o.id).max().unwrap_or(0) + 1,
+        block: block_name.to_string(),
+        at,
+        until,
+        lock_until,
+    };
+    application_state.one_offs.push(one_off.clone());
+    Ok(one_off)
+}
+
+/// Removes a booked block start before it fires.
+pub fn cancel_one_off(application_state: &mut ApplicationState, id: u32) -> Result<OneOff, String> {
+    let index = application_state
+        .one_offs
+        .iter()
+        .position(|one_off| one_off.id == id)
+        .ok_or_else(|| format!("No pending block start with id {id}"))?;
+    Ok(application_state.one_offs.remove(index))
+}
+
+/// Starts the booked blocks whose time has come and removes them from the
+/// pending list. A booking whose end passed while the daemon wasn't running
+/// is dropped. Returns the bookings that were taken off the list.
+pub fn fire_one_offs(application_state: &mut ApplicationState, now: DateTime<Local>) -> Vec<OneOff> {
+    let (due, pending) = application_state
+        .one_offs
+        .drain(..)
+        .partition(|one_off| one_off.at <= now);
+    application_state.one_offs = pending;
+
+    for one_off in &due {
+        if one_off.until.is_some_and(|until| until <= now) {
+            println!("Skipping missed one-off start of '{}'", one_off.block);
+            continue;
+        }
+        match start_block(
+            application_state,
+            &one_off.block,
+            one_off.until,
+            one_off.lock_until,
+            now,
+        ) {
+            Ok(()) => println!("One-off block started: {}", one_off.block),
+            Err(e) => eprintln!("Skipping one-off start: {e}"),
+        }
+    }
+    due
+}
+
+/// Holds every block of a configured session until `ends_at` (or for the
+/// session's default duration), locking them if the session asks for it.
+/// Nothing changes if any block can't be started.
+pub fn start_session(
+    application_state: &mut ApplicationState,
+    session_name: &str,
+    ends_at: Option<DateTime<Local>>,
+    now: DateTime<Local>,
+) -> Result<ActiveSession, String> {
+    if let Some(active) = &application_state.active_session {
+        return Err(format!(
+            "Session '{}' is already running; stop it first",
+            active.name
+        ));
+    }
+
+    let template = application_state
+        .sessions
+        .get(session_name)
+        .cloned()
+        .ok_or_else(|| format!("Session '{session_name}' not found"))?;
+    if let Some(missing) = template
+        .blocks
+        .iter()
+        .find(|name| !application_state.blocks.contains_key(*name))
+    {
+        return Err(format!("Block '{missing}' not found"));
+    }
+
+    let ends_at = ends_at.unwrap_or(now + template.duration);
+    if ends_at <= now {
+        return Err(format!("Session '{session_name}' would end immediately"));
+    }
+    if template.lock {
+        for name in &template.blocks {
+            let block = application_state.blocks.get_mut(name).unwrap();
+            block.locked_until = block.locked_until.max(Some(ends_at));
+        }
+    }
+
+    let session = ActiveSession {
+        name: session_name.to_string(),
+        blocks: template.blocks,
+        ends_at,
+        locked: template.lock,
+    };
+    application_state.active_session = Some(session.clone());
+
+    refresh_block_states(application_state, now);
+    Ok(session)
+}
+
+/// Ends the running session early, releasing its blocks unless another
+/// layer still holds them.
+pub fn stop_session(
+    application_state: &mut ApplicationState,
+    now: DateTime<Local>,
+) -> Result<(), String> {
+    let session = application_state
+        .active_session
+        .as_ref()
+        .ok_or_else(|| "No session is running".to_string())?;
+    if session.locked && session.ends_at > now {
+        return Err(format!(
+            "Session '{}' is locked until {}",
+            session.name,
+            session.ends_at.format("%Y-%m-%d %H:%M")
+        ));
+    }
+
+    application_state.active_session = None;
+    refresh_block_states(application_state, now);
+    Ok(())
+}
+
+/// Starts a pomodoro, blocking its blocks for the first work phase.
+pub fn start_pomodoro(
+    application_state: &mut ApplicationState,
+    blocks: Vec<String>,
+    work: Duration,
+    break_duration: Duration,
+    cycles: u32,
+    now: DateTime<Local>,
+) -> Result<(), String> {
+    if application_state.pomodoro.is_some() {
+        return Err("A pomodoro is already running; stop it first".to_string());
+    }
+    if blocks.is_empty() {
+        return Err("A pomodoro needs at least one block".to_string());
+    }
+    if cycles == 0 || work <= Duration::zero() || break_duration < Duration::zero() {
+        return Err("A pomodoro needs at least one cycle and a positive work time".to_string());
+    }
+    if let Some(missing) = blocks
+        .iter()
+        .find(|name| !application_state.blocks.contains_key(*name))
+    {
+        return Err(format!("Block '{missing}' not found"));
+    }
+
+    application_state.pomodoro = Some(Pomodoro {
+        blocks,
+        work,
+        break_duration,
+        cycles,
+        cycle: 1,
+        phase: PomodoroPhase::Work,
+        phase_ends_at: now + work,
+    });
+
+    refresh_block_states(application_state, now);
+    Ok(())
+}
+
+/// Stops the running pomodoro, releasing its blocks unless another layer
+/// still holds them.
+pub fn stop_pomodoro(
+    application_state: &mut ApplicationState,
+    now: DateTime<Local>,
+) -> Result<(), String> {
+    application_state
+        .pomodoro
+        .take()
+        .ok_or_else(|| "No pomodoro is running".to_string())?;
+
+    refresh_block_states(application_state, now);
+    Ok(())
+}
+
+/// Moves the pomodoro to its next phase once the current one is over. Its
+/// blocks are held only during work phases.
+fn advance_pomodoro(application_state: &mut ApplicationState, now: DateTime<Local>) {
+    let Some(pomodoro) = application_state.pomodoro.as_mut() else {
+        return;
+    };
+    if pomodoro.phase_ends_at > now {
+        return;
+    }
+
+    match pomodoro.phase {
+        PomodoroPhase::Work if pomodoro.cycle >= pomodoro.cycles => {
+            println!("Pomodoro finished");
+            application_state.pomodoro = None;
+        }
+        PomodoroPhase::Work => {
+            pomodoro.phase = PomodoroPhase::Break;
+            // Chain from the previous end so tick latency doesn't accumulate
+            pomodoro.phase_ends_at += pomodoro.break_duration;
+            println!("Pomodoro break {}/{}", pomodoro.cycle, pomodoro.cycles);
+        }
+        PomodoroPhase::Break => {
+            pomodoro.phase = PomodoroPhase::Work;
+            pomodoro.cycle += 1;
+            pomodoro.phase_ends_at += pomodoro.work;
+            println!("Pomodoro work {}/{}", pomodoro.cycle, pomodoro.cycles);
+        }
+    }
+}
+
+/// Counts `seconds` of activity on `url` against the allowance of every
+/// unblocked block that matches it, activating blocks whose allowance runs
+/// out. Returns true if any block changed state.
+pub fn record_activity(
+    application_state: &mut ApplicationState,
+    url: &str,
+    seconds: i64,
+    now: DateTime<Local>,
+) -> bool {
+    for (name, block) in application_state.blocks.iter_mut() {
+        let Some(allowance) = block.daily_allowance else {
+            continue;
+        };
+        if block.block_state != BlockState::Unblocked || !block.matches_url(url) {
+            continue;
+        }
+
+        if block.usage_date != Some(now.date_naive()) {
+            block.usage = Duration::zero();
+            block.usage_date = Some(now.date_naive());
+        }
+
+        block.usage += Duration::seconds(seconds);
+        if block.usage >= allowance {
+            block.allowance_exhausted = true;
+            println!("Daily allowance used up: {name}");
+        }
+    }
+
+    refresh_block_states(application_state, now)
+}
+
+/// Today's usage of every block with a daily allowance, to save.
+pub fn allowance_usage(application_state: &ApplicationState) -> HashMap<String, AllowanceUsage> {
+    application_state
+        .blocks
+        .iter()
+        .filter(|(_, block)| block.daily_allowance.is_some())
+        .filter_map(|(name, block)| {
+            let usage = AllowanceUsage {
+                date: block.usage_date?,
+                used: block.usage.num_seconds(),
+            };
+            Some((name.clone(), usage))
+        })
+        .collect()
+}
+
+/// Restores usage saved by [`allowance_usage`], if it was saved today,
+/// activating blocks whose allowance was already used up.
+pub fn restore_allowance_usage(
+    application_state: &mut ApplicationState,
+    saved: &HashMap<String, AllowanceUsage>,
+    now: DateTime<Local>,
+) {
+    for (name, usage) in saved {
+        let Some(block) = application_state.blocks.get_mut(name) else {
+            continue;
+        };
+        let Some(allowance) = block.daily_allowance else {
+            continue;
+        };
+        if usage.date != now.date_naive() {
+            continue;
+        }
+
+        block.usage = Duration::seconds(usage.used);
+        block.usage_date = Some(usage.date);
+        if block.usage >= allowance {
+            block.allowance_exhausted = true;
+            println!("Daily allowance used up: {name}");
+        }
+    }
+
+    refresh_block_states(application_state, now);
+}
+
+/// Expands the configured schedules into start and end events: weekly ones
+/// ordered by day and time, cron ones that fire at each match, and calendar
+/// ones with the events in their file from a day before `now` up to the
+/// schedule horizon. Where a window ends as another starts, the end comes
+/// first so back-to-back windows keep the block active.
+pub fn build_weekly_schedule(
+    schedules: &[config::Schedule],
+    exceptions: &[config::Exception],
+    now: DateTime<Local>,
+) -> Vec<Event> {
+    let mut weekly_schedule = Vec::new();
 
-    let mut weekly_schedule: Vec<Event> = Vec::new();
     for schedule in schedules {
-        for day in schedule.days {
-            let day_enum = match parse_day(&day) {
-                Ok(d) => d,
+        // Days, dates and zones are checked by config validation
+        let (Ok(days), Ok((valid_from, valid_until)), Ok(timezone), Ok(cron_window)) = (
+            schedule.weekdays(),
+            schedule.validity(),
+            schedule.zone(),
+            schedule.cron_window(),
+        ) else {
+            eprintln!("Skipping invalid schedule for block '{}'", schedule.block);
+            continue;
+        };
+        let dates = DateFilter {
+            valid_from,
+            valid_until,
+            suspended: exceptions
+                .iter()
+                .filter(|exception| exception.applies_to(schedule))
+                .filter_map(|exception| exception.date_range().ok())
+                .collect(),
+        };
+
+        if let Ok(Some((path, title))) = schedule.calendar() {
+            let from = now - Duration::days(1);
+            let until = now + Duration::days(SCHEDULE_HORIZON_DAYS);
+            let windows = match calendar::load_windows(path, title, from, until) {
+                Ok(windows) => Arc::new(windows),
                 Err(e) => {
-                    eprintln!("Skipping invalid day '{}': {}", day, e);
+                    eprintln!("Skipping calendar schedule for block '{}': {e}", schedule.block);
                     continue;
                 }
             };
-            let block_name = schedule.block.clone();
+            let window = weekly_schedule.len() / 2;
+            for action in [ScheduleAction::StartBlock, ScheduleAction::EndBlock] {
+                weekly_schedule.push(Event {
+                    block: schedule.block.clone(),
+                    recurrence: Recurrence::Calendar {
+                        windows: Arc::clone(&windows),
+                    },
+                    action,
+                    dates: dates.clone(),
+                    // The windows are already in local time
+                    timezone: None,
+                    window,
+                });
+            }
+            continue;
+        }
 
-            // Add the start time
-            let start_time = parse_time(&schedule.start);
-            weekly_schedule.push(create_event(
-                day_enum.into(),
-                start_time,
-                block_name.clone(),
-                ScheduleAction::StartBlock,
-            ));
+        if let Some((cron, duration)) = cron_window {
+            let cron = Arc::new(cron);
+            let window = weekly_schedule.len() / 2;
+            for action in [ScheduleAction::StartBlock, ScheduleAction::EndBlock] {
+                weekly_schedule.push(Event {
+                    block: schedule.block.clone(),
+                    recurrence: Recurrence::Cron {
+                        cron: Arc::clone(&cron),
+                        duration,
+                    },
+                    action,
+                    dates: dates.clone(),
+                    timezone,
+                    window,
+                });
+            }
+            continue;
+        }
+
+        let start_time = parse_time(&schedule.start);
+        let end_time = parse_time(&schedule.end);
+        // A window that ends at or before its start time runs past midnight
+        let overnight = end_time <= start_time;
 
-            // Add the end time
-            let end_time = parse_time(&schedule.end);
-            weekly_schedule.push(create_event(
-                day_enum.into(),
-                end_time,
-                block_name,
-                ScheduleAction::EndBlock,
-            ));
+        for day in days {
+            let window = weekly_schedule.len() / 2;
+            weekly_schedule.push(Event {
+                block: schedule.block.clone(),
+                recurrence: Recurrence::Weekly {
+                    day: day.into(),
+                    time: start_time,
+                    overnight_end: false,
+                },
+                action: ScheduleAction::StartBlock,
+                dates: dates.clone(),
+                timezone,
+                window,
+            });
+            weekly_schedule.push(Event {
+                block: schedule.block.clone(),
+                recurrence: Recurrence::Weekly {
+                    day: if overnight { day.succ() } else { day }.into(),
+                    time: end_time,
+                    overnight_end: overnight,
+                },
+                action: ScheduleAction::EndBlock,
+                dates: dates.clone(),
+                timezone,
+                window,
+            });
         }
     }
 
-    weekly_schedule.sort();
-    application_state.lock().unwrap().schedule = weekly_schedule.clone();
+    let sort_key = |event: &Event| {
+        let weekly = match event.recurrence {
+            Recurrence::Weekly { day, time, .. } => Some((day, time)),
+            Recurrence::Cron { .. } | Recurrence::Calendar { .. } => None,
+        };
+        (weekly, event.action == ScheduleAction::StartBlock, event.block.clone())
+    };
+    weekly_schedule.sort_by_key(sort_key);
+    weekly_schedule
+}
 
-    // Activate blocks that should be currently active based on schedule
-    activate_scheduled_blocks(&application_state, &weekly_schedule);
+/// An event firing at `at`, for the window that started on `date` in the
+/// event's own time zone.
+struct Occurrence<'a> {
+    at: DateTime<Local>,
+    event: &'a Event,
+    date: NaiveDate,
+}
 
-    application_state
+/// The events that fire on the local `date`, in order. Events in another
+/// time zone follow that zone's calendar, so they can come from its day
+/// before or after.
+fn occurrences_on(schedule: &[Event], date: NaiveDate) -> Vec<Occurrence<'_>> {
+    let mut occurrences: Vec<_> = schedule
+        .iter()
+        .flat_map(|event| {
+            // Zones are at most 26 hours apart
+            let offsets = if event.timezone.is_some() { -2..=2 } else { 0..=0 };
+            offsets
+                .flat_map(move |offset| event.fire_times(date + Duration::days(offset)))
+                .filter(move |(at, _)| at.date_naive() == date)
+                .map(move |(at, window_date)| Occurrence {
+                    at,
+                    event,
+                    date: window_date,
+                })
+        })
+        .collect();
+    // Stable, so an end still comes before a start at the same moment
+    occurrences.sort_by_key(|occurrence| occurrence.at);
+    occurrences
 }
 
-fn parse_time(time_str: &str) -> NaiveTime {
-    let parts: Vec<&str> = time_str.split(':').collect();
-    if parts.len() != 2 {
-        panic!("Invalid time format: {time_str}");
+/// The next moment after `after` that scheduled events fire, with every
+/// event due at that moment.
+pub fn next_scheduled_events(
+    schedule: &[Event],
+    after: DateTime<Local>,
+) -> Option<(DateTime<Local>, Vec<Event>)> {
+    let today = after.date_naive();
+    let mut upcoming = (0..=SCHEDULE_HORIZON_DAYS)
+        .flat_map(|offset| occurrences_on(schedule, today + Duration::days(offset)))
+        .skip_while(|occurrence| occurrence.at <= after)
+        .peekable();
+
+    let first = upcoming.next()?;
+    let mut events = vec![first.event.clone()];
+    while let Some(next) = upcoming.next_if(|next| next.at == first.at) {
+        events.push(next.event.clone());
     }
+    Some((first.at, events))
+}
 
-    let hour = parts[0].parse::<u32>().expect("Invalid hour");
-    let minute = parts[1].parse::<u32>().expect("Invalid minute");
+/// Rebuilds the weekly events from the config file's schedules plus those
+/// added at runtime.
+fn rebuild_schedule(application_state: &mut ApplicationState, now: DateTime<Local>) {
+    let schedules: Vec<config::Schedule> = application_state
+        .config_schedules
+        .iter()
+        .chain(application_state.schedule_overlay.added.iter().map(|added| &added.schedule))
+        .cloned()
+        .collect();
+    application_state.schedule =
+        build_weekly_schedule(&schedules, &application_state.exceptions, now);
+}
 
-    NaiveTime::from_hms_opt(hour, minute, 0).expect("Invalid time")
+/// The calendar files that `ics` schedules read.
+pub fn calendar_files(application_state: &ApplicationState) -> Vec<PathBuf> {
+    application_state
+        .config_schedules
+        .iter()
+        .filter_map(|schedule| schedule.ics.as_ref().map(PathBuf::from))
+        .collect()
 }
 
-fn parse_day(day_str: &str) -> Result<Weekday, String> {
-    DAY_MAP
+/// Re-reads the calendar files, e.g. after one changed, and lets the
+/// schedule layer follow any window that opened or closed because of it.
+/// Paused blocks are left alone. Returns true if any block changed.
+pub fn reload_calendars(application_state: &mut ApplicationState, now: DateTime<Local>) -> bool {
+    rebuild_schedule(application_state, now);
+
+    let changed: Vec<(String, bool)> = application_state
+        .blocks
         .iter()
-        .find(|(d, _)| *d == day_str)
-        .map(|(_, day)| day.clone())
-        .ok_or_else(|| format!("Invalid day: {day_str}"))
+        .filter(|(name, _)| !is_schedule_paused(application_state, name, now))
+        .filter_map(|(name, block)| {
+            let scheduled = is_block_currently_scheduled(name, now, &application_state.schedule);
+            (block.scheduled != scheduled).then(|| (name.clone(), scheduled))
+        })
+        .collect();
+    for (block_name, scheduled) in changed {
+        set_scheduled(application_state, &block_name, scheduled);
+    }
+    refresh_block_states(application_state, now)
 }
 
-pub fn update_block(
-    application_state: &mut ApplicationState,
+pub fn is_schedule_paused(
+    application_state: &ApplicationState,
     block_name: &str,
-    new_state: BlockState,
-    // stream: &mut UnixStream
-) {
+    now: DateTime<Local>,
+) -> bool {
+    application_state
+        .schedule_overlay
+        .pauses
+        .iter()
+        .any(|pause| pause.block == block_name && pause.until > now)
+}
+
+/// Sets whether the schedule layer holds a block. Either way a new or
+/// closed window ends any manual override of the previous one.
+fn set_scheduled(application_state: &mut ApplicationState, block_name: &str, scheduled: bool) {
     if let Some(block) = application_state.blocks.get_mut(block_name) {
-        block.block_state = new_state;
-    } else {
-        eprintln!("Block '{}' not found in application state", block_name);
+        block.scheduled = scheduled;
+        block.schedule_overridden = false;
     }
+}
 
-    // TODO: Call a function to send a message to the bridge with the updated state.
-    // send_state_to_bridge(stream, application_state);
+/// Applies a weekly schedule event to the block's schedule layer. Starts
+/// are skipped while the block's schedule is paused, and ends while another
+/// of its windows is open.
+pub fn apply_scheduled_event(
+    application_state: &mut ApplicationState,
+    event: &Event,
+    now: DateTime<Local>,
+) {
+    match event.action {
+        ScheduleAction::StartBlock if is_schedule_paused(application_state, &event.block, now) => {
+            println!("Skipping scheduled start of '{}': schedule is paused", event.block);
+        }
+        ScheduleAction::StartBlock => set_scheduled(application_state, &event.block, true),
+        ScheduleAction::EndBlock
+            if is_block_currently_scheduled(&event.block, now, &application_state.schedule) =>
+        {
+            println!(
+                "Keeping '{}' active: another scheduled window is still open",
+                event.block
+            );
+        }
+        ScheduleAction::EndBlock => set_scheduled(application_state, &event.block, false),
+    }
+    refresh_block_states(application_state, now);
 }
 
-fn create_event(
-    day: OrderableWeekday,
-    time: NaiveTime,
-    block: String,
-    action: ScheduleAction,
-) -> Event {
-    Event {
-        block,
-        day,
-        time,
-        action,
+/// Adds a schedule on top of the config file's ones, starting its block
+/// right away if the new window is already open. Returns the new id.
+pub fn add_schedule(
+    application_state: &mut ApplicationState,
+    mut schedule: config::Schedule,
+    now: DateTime<Local>,
+) -> Result<u32, String> {
+    if !application_state.blocks.contains_key(&schedule.block) {
+        return Err(format!("Block '{}' not found", schedule.block));
+    }
+    if schedule.timezone.is_none() {
+        schedule.timezone = application_state.default_timezone.clone();
+    }
+    if schedule.ics.is_some() {
+        return Err("Calendar schedules can only be set in the config file".to_string());
+    }
+    schedule.weekdays()?;
+    schedule.validity()?;
+    schedule.zone()?;
+    if schedule.cron_window()?.is_none() {
+        for (key, time) in [("start", &schedule.start), ("end", &schedule.end)] {
+            timespec::parse_time_of_day(time).map_err(|e| format!("Invalid {key} time: {e}"))?;
+        }
+    }
+    if let Some(name) = &schedule.name {
+        let taken = application_state
+            .config_schedules
+            .iter()
+            .chain(application_state.schedule_overlay.added.iter().map(|added| &added.schedule))
+            .any(|existing| existing.name.as_ref() == Some(name));
+        if taken {
+            return Err(format!("A schedule named '{name}' already exists"));
+        }
+    }
+
+    let overlay = &mut application_state.schedule_overlay;
+    let id = overlay.added.iter().map(|added| added.id).max().unwrap_or(0) + 1;
+    let block_name = schedule.block.clone();
+    overlay.added.push(AddedSchedule { id, schedule });
+    rebuild_schedule(application_state, now);
+
+    if !is_schedule_paused(application_state, &block_name, now)
+        && is_block_currently_scheduled(&block_name, now, &application_state.schedule)
+    {
+        set_scheduled(application_state, &block_name, true);
+        refresh_block_states(application_state, now);
     }
+    Ok(id)
 }
 
-fn activate_scheduled_blocks(
-    application_state: &Arc<Mutex<ApplicationState>>, 
-    weekly_schedule: &[Event]
-) {
-    let now = chrono::Local::now();
-    let current_weekday = OrderableWeekday(now.weekday());
-    let current_time = now.time();
+/// Removes a schedule added with [`add_schedule`]. If that closes an open
+/// window the schedule lets go of the block, though other layers, such as
+/// a lock, may still hold it.
+pub fn remove_schedule(
+    application_state: &mut ApplicationState,
+    id: u32,
+    now: DateTime<Local>,
+) -> Result<AddedSchedule, String> {
+    let index = application_state
+        .schedule_overlay
+        .added
+        .iter()
+        .position(|added| added.id == id)
+        .ok_or_else(|| {
+            format!(
+                "No added schedule with id {id}. Schedules from the config file can only be removed there."
+            )
+        })?;
 
-    // Find all blocks that should be currently active
-    let mut active_blocks = std::collections::HashSet::new();
+    let block_name = application_state.schedule_overlay.added[index].schedule.block.clone();
+    let was_scheduled = is_block_currently_scheduled(&block_name, now, &application_state.schedule);
+    let removed = application_state.schedule_overlay.added.remove(index);
+    rebuild_schedule(application_state, now);
 
-    for block_name in get_block_names(application_state) {
-        if is_block_currently_scheduled(&block_name, current_weekday, current_time, weekly_schedule) {
-            active_blocks.insert(block_name);
-        }
+    if was_scheduled && !is_block_currently_scheduled(&block_name, now, &application_state.schedule)
+    {
+        set_scheduled(application_state, &block_name, false);
+        refresh_block_states(application_state, now);
     }
+    Ok(removed)
+}
 
-    // Activate the blocks that should be active
-    let mut app_state = application_state.lock().unwrap();
-    for block_name in active_blocks {
-        update_block(&mut app_state, &block_name, BlockState::Blocked);
-        println!("Activated scheduled block: {}", block_name);
+/// Skips the scheduled starts of a block until `until`. If a window is
+/// holding the block it is stopped as with `shire block stop`, so locks and
+/// unblock delays still apply.
+pub fn pause_schedule(
+    application_state: &mut ApplicationState,
+    block_name: &str,
+    until: DateTime<Local>,
+    now: DateTime<Local>,
+) -> Result<Option<StopOutcome>, String> {
+    let block = application_state
+        .blocks
+        .get(block_name)
+        .ok_or_else(|| format!("Block '{block_name}' not found"))?;
+    if until <= now {
+        return Err("The pause must end in the future".to_string());
     }
+
+    let outcome = if block.scheduled && !block.schedule_overridden {
+        Some(request_stop(application_state, block_name, now)?)
+    } else {
+        None
+    };
+    // A pending stop lets go of the window once the delay has passed
+    if !matches!(outcome, Some(StopOutcome::Pending(_))) {
+        set_scheduled(application_state, block_name, false);
+    }
+
+    let pauses = &mut application_state.schedule_overlay.pauses;
+    pauses.retain(|pause| pause.block != block_name);
+    pauses.push(SchedulePause {
+        block: block_name.to_string(),
+        until,
+    });
+    refresh_block_states(application_state, now);
+    Ok(outcome)
 }
 
-fn get_block_names(application_state: &Arc<Mutex<ApplicationState>>) -> Vec<String> {
-    application_state
-        .lock()
-        .unwrap()
+/// Drops pauses that are over, letting the schedule hold blocks whose
+/// window is still open again.
+fn end_schedule_pauses(application_state: &mut ApplicationState, now: DateTime<Local>) {
+    let (ended, active): (Vec<_>, Vec<_>) = application_state
+        .schedule_overlay
+        .pauses
+        .drain(..)
+        .partition(|pause| pause.until <= now);
+    application_state.schedule_overlay.pauses = active;
+
+    for pause in ended {
+        println!("Schedule pause ended: {}", pause.block);
+        if is_block_currently_scheduled(&pause.block, now, &application_state.schedule) {
+            set_scheduled(application_state, &pause.block, true);
+        }
+    }
+}
+
+fn activate_scheduled_blocks(application_state: &mut ApplicationState, now: DateTime<Local>) {
+    let active_blocks: Vec<String> = application_state
         .blocks
         .keys()
+        .filter(|name| {
+            !is_schedule_paused(application_state, name, now)
+                && is_block_currently_scheduled(name, now, &application_state.schedule)
+        })
         .cloned()
-        .collect()
+        .collect();
+
+    for block_name in active_blocks {
+        set_scheduled(application_state, &block_name, true);
+        println!("Activated scheduled block: {}", block_name);
+    }
 }
 
-fn is_block_currently_scheduled(
+/// Whether any of the block's scheduled windows is open at `now`, so
+/// overlapping windows keep the block active until the last one ends.
+pub fn is_block_currently_scheduled(
     block_name: &str,
-    current_weekday: OrderableWeekday,
-    current_time: NaiveTime,
-    weekly_schedule: &[Event]
+    now: DateTime<Local>,
+    weekly_schedule: &[Event],
 ) -> bool {
-    // Find the most recent event for this block that occurred before or at current time
-    let mut most_recent_event = None;
-    
-    // Look for events today or earlier in the week
-    for event in weekly_schedule.iter().rev() {
-        if event.block == block_name {
-            if event.day < current_weekday || (event.day == current_weekday && event.time <= current_time) {
-                most_recent_event = Some(event);
-                break;
-            }
+    // Calendar events can last any length of time
+    let in_calendar_window = weekly_schedule.iter().any(|event| {
+        let Recurrence::Calendar { windows } = &event.recurrence else {
+            return false;
+        };
+        event.block == block_name
+            && windows.iter().any(|(start, end)| {
+                *start <= now && now < *end && event.dates.allows(start.date_naive())
+            })
+    });
+    // Other windows are at most a day long, but another zone's day can
+    // start up to two days later than ours
+    let today = now.date_naive();
+    in_calendar_window || (0..=3).any(|offset| {
+        occurrences_on(weekly_schedule, today - Duration::days(offset))
+            .iter()
+            .filter(|start| {
+                start.event.block == block_name
+                    && start.event.action == ScheduleAction::StartBlock
+                    && start.at <= now
+            })
+            .any(|start| {
+                start
+                    .event
+                    .window_end(weekly_schedule, start.at, start.date)
+                    .is_some_and(|end_at| end_at > now)
+            })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, TimeZone};
+
+    fn state_with_block(name: &str) -> ApplicationState {
+        let mut blocks = HashMap::new();
+        blocks.insert(
+            name.to_string(),
+            Block {
+                blacklist: Some(vec!["example.com".to_string()]),
+                ..Default::default()
+            },
+        );
+        ApplicationState {
+            blocks,
+            ..Default::default()
         }
     }
-    
-    // If no event found this week, look at the end of last week
-    if most_recent_event.is_none() {
-        for event in weekly_schedule.iter().rev() {
-            if event.block == block_name {
-                most_recent_event = Some(event);
+
+    #[test]
+    fn test_timed_block_ends_automatically() {
+        let now = Local.with_ymd_and_hms(2025, 9, 15, 9, 0, 0).unwrap();
+        let mut state = state_with_block("focus");
+        start_block(&mut state, "focus", Some(now + Duration::minutes(90)), None, now).unwrap();
+
+        assert!(!expire_timers(&mut state, now + Duration::minutes(89)));
+        assert_eq!(state.blocks["focus"].block_state, BlockState::Blocked);
+
+        assert!(expire_timers(&mut state, now + Duration::minutes(90)));
+        assert_eq!(state.blocks["focus"].block_state, BlockState::Unblocked);
+    }
+
+    #[test]
+    fn test_timed_block_can_be_stopped_early_unless_locked() {
+        let now = Local.with_ymd_and_hms(2025, 9, 15, 9, 0, 0).unwrap();
+        let mut state = state_with_block("focus");
+        let until = Some(now + Duration::hours(2));
+
+        start_block(&mut state, "focus", until, None, now).unwrap();
+        assert!(stop_block(&mut state, "focus", now).is_ok());
+
+        start_block(&mut state, "focus", until, Some(now + Duration::hours(1)), now).unwrap();
+        assert!(stop_block(&mut state, "focus", now).is_err());
+        assert!(stop_block(&mut state, "focus", now + Duration::hours(1)).is_ok());
+    }
+
+    #[test]
+    fn test_timed_block_waits_for_lock_to_expire() {
+        let now = Local.with_ymd_and_hms(2025, 9, 15, 9, 0, 0).unwrap();
+        let mut state = state_with_block("focus");
+        let until = Some(now + Duration::minutes(30));
+        start_block(&mut state, "focus", until, Some(now + Duration::hours(1)), now).unwrap();
+
+        // Only the layers holding the block change
+        assert!(expire_timers(&mut state, now + Duration::minutes(45)));
+        assert_eq!(state.blocks["focus"].active_because, vec![Layer::Lock]);
+        assert!(expire_timers(&mut state, now + Duration::hours(1)));
+        assert_eq!(state.blocks["focus"].block_state, BlockState::Unblocked);
+    }
+
+    #[test]
+    fn test_unblock_delay_defers_stop_until_it_passes() {
+        let now = Local.with_ymd_and_hms(2025, 9, 15, 9, 0, 0).unwrap();
+        let mut state = state_with_block("focus");
+        state.blocks.get_mut("focus").unwrap().unblock_delay = Some(Duration::minutes(15));
+        start_block(&mut state, "focus", None, None, now).unwrap();
+
+        let outcome = request_stop(&mut state, "focus", now).unwrap();
+        assert_eq!(outcome, StopOutcome::Pending(now + Duration::minutes(15)));
+        // Asking again doesn't restart the countdown
+        let outcome = request_stop(&mut state, "focus", now + Duration::minutes(5)).unwrap();
+        assert_eq!(outcome, StopOutcome::Pending(now + Duration::minutes(15)));
+
+        assert!(!expire_timers(&mut state, now + Duration::minutes(14)));
+        assert!(expire_timers(&mut state, now + Duration::minutes(15)));
+        assert_eq!(state.blocks["focus"].block_state, BlockState::Unblocked);
+    }
+
+    #[test]
+    fn test_pending_unblock_can_be_cancelled() {
+        let now = Local.with_ymd_and_hms(2025, 9, 15, 9, 0, 0).unwrap();
+        let mut state = state_with_block("focus");
+        state.blocks.get_mut("focus").unwrap().unblock_delay = Some(Duration::minutes(15));
+        start_block(&mut state, "focus", None, None, now).unwrap();
+
+        request_stop(&mut state, "focus", now).unwrap();
+        cancel_pending_unblock(&mut state, "focus").unwrap();
+        assert!(cancel_pending_unblock(&mut state, "focus").is_err());
+
+        assert!(!expire_timers(&mut state, now + Duration::hours(1)));
+        assert_eq!(state.blocks["focus"].block_state, BlockState::Blocked);
+    }
+
+    #[test]
+    fn test_matches_url_respects_whitelist() {
+        let block = Block {
+            whitelist: Some(vec!["youtube.com/watch?v=*".to_string()]),
+            blacklist: Some(vec!["youtube.com".to_string()]),
+            ..Default::default()
+        };
+
+        assert!(block.matches_url("https://www.youtube.com/"));
+        assert!(!block.matches_url("https://www.youtube.com/watch?v=abc"));
+        assert!(!block.matches_url("https://example.com/"));
+    }
+
+    #[test]
+    fn test_daily_allowance_blocks_until_midnight() {
+        let now = Local.with_ymd_and_hms(2025, 9, 15, 9, 0, 0).unwrap();
+        let mut state = state_with_block("news");
+        let block = state.blocks.get_mut("news").unwrap();
+        block.daily_allowance = Some(Duration::minutes(20));
+        block.usage_date = Some(now.date_naive());
+
+        assert!(!record_activity(&mut state, "https://example.com/a", 15 * 60, now));
+        assert!(!record_activity(&mut state, "https://other.com/", 15 * 60, now));
+        assert_eq!(state.blocks["news"].allowance_left(), Some(Duration::minutes(5)));
+
+        assert!(record_activity(&mut state, "https://example.com/b", 5 * 60, now));
+        assert_eq!(state.blocks["news"].block_state, BlockState::Blocked);
+        assert!(stop_block(&mut state, "news", now).is_err());
+
+        let midnight = Local.with_ymd_and_hms(2025, 9, 16, 0, 0, 0).unwrap();
+        assert!(expire_timers(&mut state, midnight));
+        assert_eq!(state.blocks["news"].block_state, BlockState::Unblocked);
+        assert_eq!(state.blocks["news"].allowance_left(), Some(Duration::minutes(20)));
+    }
+
+    #[test]
+    fn test_pomodoro_alternates_phases() {
+        let now = Local.with_ymd_and_hms(2025, 9, 15, 9, 0, 0).unwrap();
+        let mut state = state_with_block("focus");
+        let work = Duration::minutes(25);
+        let rest = Duration::minutes(5);
+        start_pomodoro(&mut state, vec!["focus".to_string()], work, rest, 2, now).unwrap();
+        assert_eq!(state.blocks["focus"].block_state, BlockState::Blocked);
+
+        let break_start = now + work;
+        assert!(expire_timers(&mut state, break_start));
+        assert_eq!(state.blocks["focus"].block_state, BlockState::Unblocked);
+        assert_eq!(state.pomodoro.as_ref().unwrap().phase, PomodoroPhase::Break);
+
+        let second_work = break_start + rest;
+        assert!(expire_timers(&mut state, second_work));
+        assert_eq!(state.blocks["focus"].block_state, BlockState::Blocked);
+        assert_eq!(state.pomodoro.as_ref().unwrap().cycle, 2);
+
+        assert!(expire_timers(&mut state, second_work + work));
+        assert_eq!(state.blocks["focus"].block_state, BlockState::Unblocked);
+        assert!(state.pomodoro.is_none());
+    }
+
+    #[test]
+    fn test_pomodoro_rejects_unknown_blocks() {
+        let now = Local.with_ymd_and_hms(2025, 9, 15, 9, 0, 0).unwrap();
+        let mut state = state_with_block("focus");
+        let error = start_pomodoro(
+            &mut state,
+            vec!["missing".to_string()],
+            Duration::minutes(25),
+            Duration::minutes(5),
+            4,
+            now,
+        )
+        .unwrap_err();
+        assert!(error.contains("'missing' not found"));
+    }
+
+    #[test]
+    fn test_session_starts_blocks_together_and_ends_cleanly() {
+        let now = Local.with_ymd_and_hms(2025, 9, 15, 9, 0, 0).unwrap();
+        let mut state = state_with_block("finance");
+        state.blocks.insert("news".to_string(), Block::default());
+        state.sessions.insert(
+            "deep".to_string(),
+            SessionTemplate {
+                blocks: vec!["finance".to_string(), "news".to_string()],
+                duration: Duration::hours(2),
+                lock: true,
+            },
+        );
+
+        let session = start_session(&mut state, "deep", None, now).unwrap();
+        assert_eq!(session.ends_at, now + Duration::hours(2));
+        assert!(state.blocks.values().all(|b| b.block_state == BlockState::Blocked));
+        assert!(stop_session(&mut state, now).is_err());
+        assert!(stop_block(&mut state, "news", now).is_err());
+
+        assert!(expire_timers(&mut state, now + Duration::hours(2)));
+        assert!(state.blocks.values().all(|b| b.block_state == BlockState::Unblocked));
+        assert!(state.active_session.is_none());
+    }
+
+    #[test]
+    fn test_session_with_missing_block_changes_nothing() {
+        let now = Local.with_ymd_and_hms(2025, 9, 15, 9, 0, 0).unwrap();
+        let mut state = state_with_block("finance");
+        state.sessions.insert(
+            "deep".to_string(),
+            SessionTemplate {
+                blocks: vec!["finance".to_string(), "missing".to_string()],
+                duration: Duration::hours(2),
+                lock: false,
+            },
+        );
+
+        assert!(start_session(&mut state, "deep", None, now).is_err());
+        assert_eq!(state.blocks["finance"].block_state, BlockState::Unblocked);
+        assert!(state.active_session.is_none());
+    }
+
+    #[test]
+    fn test_one_off_starts_at_its_time() {
+        let now = Local.with_ymd_and_hms(2026, 10, 19, 9, 0, 0).unwrap();
+        let mut state = state_with_block("focus");
+        let at = now + Duration::hours(5);
+
+        assert!(add_one_off(&mut state, "focus", now, None, None, now).is_err());
+        assert!(add_one_off(&mut state, "focus", at, Some(at), None, now).is_err());
+        let first = add_one_off(&mut state, "focus", at, Some(at + Duration::hours(2)), None, now)
+            .unwrap();
+        let second = add_one_off(&mut state, "focus", at, None, None, now).unwrap();
+        assert_eq!((first.id, second.id), (1, 2));
+        assert_eq!(cancel_one_off(&mut state, 2).unwrap(), second);
+        assert!(cancel_one_off(&mut state, 2).is_err());
+
+        assert!(fire_one_offs(&mut state, at - Duration::seconds(1)).is_empty());
+        assert_eq!(state.blocks["focus"].block_state, BlockState::Unblocked);
+
+        assert_eq!(fire_one_offs(&mut state, at), vec![first]);
+        assert!(state.one_offs.is_empty());
+        assert_eq!(state.blocks["focus"].block_state, BlockState::Blocked);
+        assert!(expire_timers(&mut state, at + Duration::hours(2)));
+        assert_eq!(state.blocks["focus"].block_state, BlockState::Unblocked);
+    }
+
+    #[test]
+    fn test_schedule_overlay_add_remove_and_pause() {
+        let now = Local.with_ymd_and_hms(2025, 9, 15, 9, 0, 0).unwrap();
+        let mut state = state_with_block("focus");
+        let all_day = |name: Option<&str>| config::Schedule {
+            name: name.map(String::from),
+            block: "focus".to_string(),
+            days: vec!["daily".to_string()],
+            start: "0:00".to_string(),
+            end: "0:00".to_string(),
+            ..Default::default()
+        };
+
+        let id = add_schedule(&mut state, all_day(Some("always")), now).unwrap();
+        assert_eq!(state.blocks["focus"].block_state, BlockState::Blocked);
+        assert!(add_schedule(&mut state, all_day(Some("always")), now).is_err());
+
+        assert_eq!(
+            pause_schedule(&mut state, "focus", now + Duration::hours(1), now).unwrap(),
+            Some(StopOutcome::Stopped)
+        );
+        assert_eq!(state.blocks["focus"].block_state, BlockState::Unblocked);
+        let start = state.schedule[0].clone();
+        apply_scheduled_event(&mut state, &start, now);
+        assert_eq!(state.blocks["focus"].block_state, BlockState::Unblocked);
+        assert!(expire_timers(&mut state, now + Duration::hours(1)));
+        assert_eq!(state.blocks["focus"].block_state, BlockState::Blocked);
+
+        // Removing the window doesn't get around a lock
+        lock_block(&mut state, "focus", now + Duration::hours(3), now).unwrap();
+        assert!(remove_schedule(&mut state, id, now + Duration::hours(2)).is_ok());
+        assert!(state.schedule.is_empty());
+        assert_eq!(state.blocks["focus"].active_because, vec![Layer::Lock]);
+        assert!(expire_timers(&mut state, now + Duration::hours(3)));
+        assert_eq!(state.blocks["focus"].block_state, BlockState::Unblocked);
+    }
+
+    fn schedule_from(contents: &str) -> Vec<Event> {
+        let config = crate::config::parse_config_str(contents, std::path::Path::new(".")).unwrap();
+        let now = Local.with_ymd_and_hms(2025, 9, 15, 9, 0, 0).unwrap();
+        build_weekly_schedule(&config.schedule, &config.exceptions, now)
+    }
+
+    #[test]
+    fn test_overnight_window_ends_the_next_day() {
+        let schedule = schedule_from(
+            r#"
+            [[blocks]]
+            name = "late"
+
+            [[schedule]]
+            block = "late"
+            days = ["Fri"]
+            start = "22:00"
+            end = "6:00"
+            "#,
+        );
+        let friday = Local.with_ymd_and_hms(2026, 12, 18, 12, 0, 0).unwrap();
+
+        let (at, events) = next_scheduled_events(&schedule, friday).unwrap();
+        assert_eq!(at, friday + Duration::hours(10));
+        assert_eq!(events[0].action, ScheduleAction::StartBlock);
+        let (at, events) = next_scheduled_events(&schedule, at).unwrap();
+        assert_eq!(at, friday + Duration::hours(18));
+        assert_eq!(events[0].action, ScheduleAction::EndBlock);
+
+        assert!(is_block_currently_scheduled("late", friday + Duration::hours(13), &schedule));
+        assert!(!is_block_currently_scheduled("late", friday + Duration::hours(19), &schedule));
+    }
+
+    #[test]
+    fn test_overlapping_windows_merge() {
+        let schedule = schedule_from(
+            r#"
+            [[blocks]]
+            name = "focus"
+
+            [[schedule]]
+            block = "focus"
+            days = ["Mon"]
+            start = "9:00"
+            end = "12:00"
+
+            [[schedule]]
+            block = "focus"
+            days = ["Mon"]
+            start = "11:00"
+            end = "14:00"
+            "#,
+        );
+        let monday = |hour| Local.with_ymd_and_hms(2026, 12, 21, hour, 0, 0).unwrap();
+        let mut state = state_with_block("focus");
+        state.schedule = schedule.clone();
+        state.blocks.get_mut("focus").unwrap().scheduled = true;
+
+        // The first window ends while the second is still open
+        let (at, events) = next_scheduled_events(&schedule, monday(11)).unwrap();
+        assert_eq!(at, monday(12));
+        assert!(is_block_currently_scheduled("focus", at, &schedule));
+        apply_scheduled_event(&mut state, &events[0], at);
+        assert_eq!(state.blocks["focus"].block_state, BlockState::Blocked);
+
+        let (at, events) = next_scheduled_events(&schedule, at).unwrap();
+        assert_eq!(at, monday(14));
+        apply_scheduled_event(&mut state, &events[0], at);
+        assert_eq!(state.blocks["focus"].block_state, BlockState::Unblocked);
+    }
+
+    #[test]
+    fn test_manual_and_scheduled_layers_are_separate() {
+        let schedule = schedule_from(
+            r#"
+            [[blocks]]
+            name = "focus"
+
+            [[schedule]]
+            block = "focus"
+            days = ["Mon"]
+            start = "9:00"
+            end = "12:00"
+            "#,
+        );
+        let monday = |hour| Local.with_ymd_and_hms(2026, 12, 21, hour, 0, 0).unwrap();
+        let mut state = state_with_block("focus");
+        state.schedule = schedule.clone();
+        let (start, end) = (&schedule[0], &schedule[1]);
+
+        // A manual start outlives the window
+        apply_scheduled_event(&mut state, start, monday(9));
+        start_block(&mut state, "focus", None, None, monday(10)).unwrap();
+        assert_eq!(
+            state.blocks["focus"].active_because,
+            vec![Layer::Manual, Layer::Schedule]
+        );
+        apply_scheduled_event(&mut state, end, monday(12));
+        assert_eq!(state.blocks["focus"].active_because, vec![Layer::Manual]);
+        stop_block(&mut state, "focus", monday(13)).unwrap();
+        assert_eq!(state.blocks["focus"].block_state, BlockState::Unblocked);
+
+        // A manual stop lasts until the window ends, not past the next start
+        apply_scheduled_event(&mut state, start, monday(9) + Duration::weeks(1));
+        stop_block(&mut state, "focus", monday(10) + Duration::weeks(1)).unwrap();
+        assert_eq!(state.blocks["focus"].block_state, BlockState::Unblocked);
+        apply_scheduled_event(&mut state, end, monday(12) + Duration::weeks(1));
+        apply_scheduled_event(&mut state, start, monday(9) + Duration::weeks(2));
+        assert_eq!(state.blocks["focus"].active_because, vec![Layer::Schedule]);
+    }
+
+    #[test]
+    fn test_calendar_schedules_follow_their_file() {
+        let dir = std::env::temp_dir().join(format!("shire-calendar-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let write_event = |start: DateTime<Local>, end: DateTime<Local>| {
+            let format = |at: DateTime<Local>| at.to_utc().format("%Y%m%dT%H%M%SZ").to_string();
+            let event = format!(
+                "BEGIN:VEVENT\nSUMMARY:Focus\nDTSTART:{}\nDTEND:{}\nEND:VEVENT\n",
+                format(start),
+                format(end)
+            );
+            let calendar = format!("BEGIN:VCALENDAR\nVERSION:2.0\n{event}END:VCALENDAR\n");
+            std::fs::write(dir.join("focus.ics"), calendar).unwrap();
+        };
+        let config = crate::config::parse_config_str(
+            r#"
+            [[blocks]]
+            name = "focus"
+
+            [[schedule]]
+            block = "focus"
+            ics = "focus.ics"
+            title = "focus"
+            "#,
+            &dir,
+        )
+        .unwrap();
+        let now = Local.with_ymd_and_hms(2025, 9, 15, 9, 0, 0).unwrap();
+        let mut state = state_with_block("focus");
+        state.config_schedules = config.schedule;
+
+        // A three-day event, already under way
+        write_event(now - Duration::hours(1), now + Duration::days(3));
+        assert!(reload_calendars(&mut state, now));
+        assert_eq!(state.blocks["focus"].active_because, vec![Layer::Schedule]);
+        let (at, events) = next_scheduled_events(&state.schedule, now).unwrap();
+        assert_eq!(at.timestamp(), (now + Duration::days(3)).timestamp());
+        assert_eq!(events[0].action, ScheduleAction::EndBlock);
+
+        // Moved to tomorrow
+        write_event(now + Duration::days(1), now + Duration::days(2));
+        assert!(reload_calendars(&mut state, now));
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(state.blocks["focus"].block_state, BlockState::Unblocked);
+        let (at, events) = next_scheduled_events(&state.schedule, now).unwrap();
+        assert_eq!(at.timestamp(), (now + Duration::days(1)).timestamp());
+        assert_eq!(events[0].action, ScheduleAction::StartBlock);
+    }
+
+    #[test]
+    fn test_cron_schedules_fire_at_each_match() {
+        let schedule = schedule_from(
+            r#"
+            [[blocks]]
+            name = "feeds"
+
+            [[schedule]]
+            block = "feeds"
+            cron = "0 9 * * MON#1"
+            duration = "3h"
+
+            [[schedule]]
+            block = "feeds"
+            cron = "0 */8 * * SAT"
+            duration = "30m"
+
+            [[schedule]]
+            block = "feeds"
+            cron = "0 23 L * *"
+            duration = "2h"
+            "#,
+        );
+        let at = |day, hour, minute| Local.with_ymd_and_hms(2026, 10, day, hour, minute, 0).unwrap();
+        let mut fired = Vec::new();
+        // Skip the end of September's last window
+        let mut after = at(1, 2, 0);
+        while let Some((next, events)) = next_scheduled_events(&schedule, after) {
+            if next > at(31, 23, 59) {
                 break;
             }
+            fired.push((next, events[0].action.clone()));
+            after = next;
         }
+
+        // The first Monday of October 2026 is the 5th
+        assert_eq!(fired[0], (at(3, 0, 0), ScheduleAction::StartBlock));
+        assert_eq!(fired[1], (at(3, 0, 30), ScheduleAction::EndBlock));
+        assert!(fired.contains(&(at(5, 9, 0), ScheduleAction::StartBlock)));
+        assert!(fired.contains(&(at(5, 12, 0), ScheduleAction::EndBlock)));
+        assert!(fired.contains(&(at(31, 16, 30), ScheduleAction::EndBlock)));
+        // Five Saturdays of three windows, one Monday and the last day
+        assert_eq!(fired.len(), 2 * (5 * 3 + 1) + 1);
+        assert_eq!(fired.last(), Some(&(at(31, 23, 0), ScheduleAction::StartBlock)));
+
+        assert!(is_block_currently_scheduled("feeds", at(5, 11, 59), &schedule));
+        assert!(!is_block_currently_scheduled("feeds", at(5, 12, 0), &schedule));
+        // The month's last window runs into November
+        let november = Local.with_ymd_and_hms(2026, 11, 1, 0, 30, 0).unwrap();
+        assert!(is_block_currently_scheduled("feeds", november, &schedule));
+        assert!(!is_block_currently_scheduled("feeds", november + Duration::hours(1), &schedule));
+    }
+
+    #[test]
+    fn test_schedules_follow_their_time_zone() {
+        let schedule = schedule_from(
+            r#"
+            timezone = "Asia/Tokyo"
+
+            [[blocks]]
+            name = "work"
+
+            [[blocks]]
+            name = "late"
+
+            [[schedule]]
+            block = "work"
+            days = ["Mon"]
+            start = "9:00"
+            end = "10:00"
+
+            [[schedule]]
+            block = "late"
+            days = ["Sun"]
+            start = "23:00"
+            end = "23:30"
+            timezone = "America/Los_Angeles"
+            "#,
+        );
+        let tokyo = |day, hour| {
+            chrono_tz::Asia::Tokyo
+                .with_ymd_and_hms(2026, 12, day, hour, 0, 0)
+                .unwrap()
+                .with_timezone(&Local)
+        };
+
+        let (at, events) = next_scheduled_events(&schedule, tokyo(20, 0)).unwrap();
+        assert_eq!(at, tokyo(21, 9));
+        assert_eq!(events[0].block, "work");
+        assert!(is_block_currently_scheduled("work", tokyo(21, 9) + Duration::minutes(30), &schedule));
+
+        // Sunday 23:00 in Los Angeles is Monday 16:00 in Tokyo
+        let (at, events) = next_scheduled_events(&schedule, tokyo(21, 10)).unwrap();
+        assert_eq!(at, tokyo(21, 16));
+        assert_eq!(events[0].block, "late");
+    }
+
+    #[test]
+    fn test_exceptions_and_validity_suspend_windows() {
+        let schedule = schedule_from(
+            r#"
+            [[blocks]]
+            name = "work"
+
+            [[blocks]]
+            name = "gym"
+
+            [[schedule]]
+            block = "work"
+            days = ["weekdays"]
+            start = "9:00"
+            end = "17:00"
+
+            [[schedule]]
+            name = "evening"
+            block = "gym"
+            days = ["daily"]
+            start = "18:00"
+            end = "19:00"
+            valid_until = "2026-12-25"
+
+            [[exceptions]]
+            from = "2026-12-24"
+            until = "2026-12-26"
+            blocks = ["work"]
+            "#,
+        );
+        let at = |day, hour| Local.with_ymd_and_hms(2026, 12, day, hour, 0, 0).unwrap();
+
+        assert!(is_block_currently_scheduled("work", at(23, 10), &schedule));
+        assert!(!is_block_currently_scheduled("work", at(24, 10), &schedule));
+        // Other blocks keep their schedule during the exception
+        assert!(is_block_currently_scheduled("gym", at(25, 18), &schedule));
+        assert!(!is_block_currently_scheduled("gym", at(26, 18), &schedule));
+
+        // Skips the holiday, the weekend and the expired gym schedule
+        let (next, events) = next_scheduled_events(&schedule, at(25, 19)).unwrap();
+        assert_eq!(next, at(28, 9));
+        assert_eq!(events[0].block, "work");
     }
-    
-    // If the most recent event was a StartBlock, the block should be active
-    most_recent_event
-        .map(|event| matches!(event.action, ScheduleAction::StartBlock))
-        .unwrap_or(false)
 }
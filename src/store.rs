@@ -0,0 +1,43 @@
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Where the daemon keeps state that must survive a restart:
+/// `$XDG_STATE_HOME/shire`, or `~/.local/state/shire`.
+pub fn state_dir() -> Result<PathBuf, String> {
+    let state_home = match env::var_os("XDG_STATE_HOME").map(PathBuf::from) {
+        Some(dir) if dir.is_absolute() => dir,
+        _ => PathBuf::from(env::var("HOME").map_err(|e| format!("HOME is not set: {e}"))?)
+            .join(".local/state"),
+    };
+    Ok(state_home.join("shire"))
+}
+
+/// Reads a JSON file written by [`save`]. A missing file gives the default.
+pub fn load<T: DeserializeOwned + Default>(path: &Path) -> Result<T, String> {
+    match fs::read_to_string(path) {
+        Ok(contents) => serde_json::from_str(&contents)
+            .map_err(|e| format!("Failed to parse '{}': {}", path.display(), e)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(T::default()),
+        Err(e) => Err(format!("Failed to read '{}': {}", path.display(), e)),
+    }
+}
+
+/// Writes `value` as JSON, replacing the file in one step so a crash never
+/// leaves it half written.
+pub fn save<T: Serialize>(path: &Path, value: &T) -> Result<(), String> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)
+            .map_err(|e| format!("Failed to create '{}': {}", dir.display(), e))?;
+    }
+
+    let contents = serde_json::to_string_pretty(value)
+        .map_err(|e| format!("Failed to serialize '{}': {}", path.display(), e))?;
+    let temp_path = path.with_extension("tmp");
+    fs::write(&temp_path, contents + "\n")
+        .and_then(|_| fs::rename(&temp_path, path))
+        .map_err(|e| format!("Failed to write '{}': {}", path.display(), e))
+}
@@ -0,0 +1,524 @@
+use chrono::{DateTime, Duration, Local, NaiveDate, NaiveTime, TimeZone, Weekday};
+use chrono_tz::Tz;
+use croner::Cron;
+use croner::parser::{CronParser, Seconds, Year};
+
+const DURATION_UNITS: &[(&str, i64)] = &[
+    ("s", 1),
+    ("sec", 1),
+    ("secs", 1),
+    ("second", 1),
+    ("seconds", 1),
+    ("m", 60),
+    ("min", 60),
+    ("mins", 60),
+    ("minute", 60),
+    ("minutes", 60),
+    ("h", 3600),
+    ("hr", 3600),
+    ("hrs", 3600),
+    ("hour", 3600),
+    ("hours", 3600),
+    ("d", 86400),
+    ("day", 86400),
+    ("days", 86400),
+];
+
+/// Parses a duration such as "45m", "1h30m", "1h 30m" or "2 hours". A bare
+/// number is treated as minutes so that `--lock 60` keeps working.
+pub fn parse_duration(input: &str) -> Result<Duration, String> {
+    let trimmed = input.trim();
+    if trimmed.is_empty() {
+        return Err("Duration is empty".to_string());
+    }
+
+    if trimmed.chars().all(|c| c.is_ascii_digit()) {
+        let minutes = parse_number(trimmed)
+            .ok_or_else(|| format!("Invalid duration '{trimmed}': number is too large"))?;
+        return Ok(Duration::minutes(minutes.into()));
+    }
+
+    let lowered = trimmed.to_lowercase();
+    let mut rest = lowered.as_str();
+    let mut total_secs: i64 = 0;
+
+    while !rest.is_empty() {
+        let digits_len = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        if digits_len == 0 {
+            return Err(format!(
+                "Invalid duration '{trimmed}': expected a number before '{rest}'"
+            ));
+        }
+        let amount: i64 = rest[..digits_len]
+            .parse()
+            .map_err(|_| format!("Invalid duration '{trimmed}': number is too large"))?;
+        rest = rest[digits_len..].trim_start();
+
+        let unit_len = rest
+            .find(|c: char| !c.is_ascii_alphabetic())
+            .unwrap_or(rest.len());
+        let unit = &rest[..unit_len];
+        if unit.is_empty() {
+            return Err(format!(
+                "Invalid duration '{trimmed}': missing unit after '{amount}' (e.g. h, m, s)"
+            ));
+        }
+        let seconds_per_unit = DURATION_UNITS
+            .iter()
+            .find(|(name, _)| *name == unit)
+            .map(|(_, secs)| *secs)
+            .ok_or_else(|| {
+                format!(
+                    "Invalid duration '{trimmed}': unknown unit '{unit}' (expected d, h, m or s)"
+                )
+            })?;
+        rest = rest[unit_len..].trim_start();
+
+        total_secs = amount
+            .checked_mul(seconds_per_unit)
+            .and_then(|secs| total_secs.checked_add(secs))
+            .ok_or_else(|| format!("Invalid duration '{trimmed}': duration is too long"))?;
+    }
+
+    Duration::try_seconds(total_secs)
+        .ok_or_else(|| format!("Invalid duration '{trimmed}': duration is out of range"))
+}
+
+/// Parses a wall-clock time: 24-hour "17:00" or "9:30", or 12-hour "5pm",
+/// "5 pm" or "5:30am".
+pub fn parse_time_of_day(input: &str) -> Result<NaiveTime, String> {
+    let trimmed = input.trim();
+    if trimmed.is_empty() {
+        return Err("Time is empty".to_string());
+    }
+
+    let lowered = trimmed.to_lowercase();
+    let (clock, meridiem) = if let Some(clock) = lowered.strip_suffix("am") {
+        (clock.trim_end(), Some(false))
+    } else if let Some(clock) = lowered.strip_suffix("pm") {
+        (clock.trim_end(), Some(true))
+    } else {
+        (lowered.as_str(), None)
+    };
+
+    let (hour_str, minute_str) = match clock.split_once(':') {
+        Some((hour, minute)) => (hour, Some(minute)),
+        None if meridiem.is_some() => (clock, None),
+        None => {
+            return Err(format!(
+                "Invalid time '{trimmed}': expected HH:MM (e.g. 17:00) or a 12-hour time (e.g. 5pm)"
+            ));
+        }
+    };
+
+    let hour: u32 = parse_number(hour_str)
+        .ok_or_else(|| format!("Invalid time '{trimmed}': '{hour_str}' is not a valid hour"))?;
+    let minute: u32 = match minute_str {
+        Some(m) if m.len() == 2 => parse_number(m),
+        Some(_) => None,
+        None => Some(0),
+    }
+    .ok_or_else(|| {
+        format!(
+            "Invalid time '{trimmed}': minutes must be two digits, got '{}'",
+            minute_str.unwrap_or_default()
+        )
+    })?;
+
+    let hour = match meridiem {
+        Some(pm) => {
+            if !(1..=12).contains(&hour) {
+                return Err(format!(
+                    "Invalid time '{trimmed}': hour must be between 1 and 12 with am/pm"
+                ));
+            }
+            (hour % 12) + if pm { 12 } else { 0 }
+        }
+        None => {
+            if hour > 23 {
+                return Err(format!(
+                    "Invalid time '{trimmed}': hour {hour} is out of range (0-23)"
+                ));
+            }
+            hour
+        }
+    };
+
+    if minute > 59 {
+        return Err(format!(
+            "Invalid time '{trimmed}': minute {minute} is out of range (0-59)"
+        ));
+    }
+
+    Ok(NaiveTime::from_hms_opt(hour, minute, 0).expect("hour and minute are in range"))
+}
+
+const WEEK: [(Weekday, &str); 7] = [
+    (Weekday::Mon, "monday"),
+    (Weekday::Tue, "tuesday"),
+    (Weekday::Wed, "wednesday"),
+    (Weekday::Thu, "thursday"),
+    (Weekday::Fri, "friday"),
+    (Weekday::Sat, "saturday"),
+    (Weekday::Sun, "sunday"),
+];
+
+/// Parses one entry of a schedule's `days`: a day ("Mon" or "Monday"), a
+/// range ("Mon-Fri", or "Fri-Mon" wrapping over the weekend), or one of
+/// "weekdays", "weekends" and "daily". Case doesn't matter.
+pub fn parse_days(input: &str) -> Result<Vec<Weekday>, String> {
+    let lower = input.trim().to_lowercase();
+    let days = match lower.as_str() {
+        "weekdays" => Some(week_range(Weekday::Mon, Weekday::Fri)),
+        "weekends" => Some(week_range(Weekday::Sat, Weekday::Sun)),
+        "daily" => Some(week_range(Weekday::Mon, Weekday::Sun)),
+        _ => match lower.split_once('-') {
+            Some((first, last)) => parse_weekday(first)
+                .zip(parse_weekday(last))
+                .map(|(first, last)| week_range(first, last)),
+            None => parse_weekday(&lower).map(|day| vec![day]),
+        },
+    };
+
+    days.ok_or_else(|| {
+        format!(
+            "Invalid day: '{input}'. Valid days are: Mon to Sun or full names like Monday, \
+             ranges like Mon-Fri, weekdays, weekends and daily"
+        )
+    })
+}
+
+fn parse_weekday(input: &str) -> Option<Weekday> {
+    let input = input.trim();
+    WEEK.iter()
+        .find(|(_, name)| {
+            input.len() >= 3 && name.starts_with(input) && (input.len() == 3 || input == *name)
+        })
+        .map(|(day, _)| *day)
+}
+
+/// The days from `first` to `last` inclusive, wrapping past Sunday.
+fn week_range(first: Weekday, last: Weekday) -> Vec<Weekday> {
+    let mut days = vec![first];
+    let mut day = first;
+    while day != last {
+        day = day.succ();
+        days.push(day);
+    }
+    days
+}
+
+/// Parses a calendar date written as "2026-12-25".
+pub fn parse_date(input: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(input.trim(), "%Y-%m-%d")
+        .map_err(|_| format!("Invalid date '{input}': expected YYYY-MM-DD (e.g. 2026-12-25)"))
+}
+
+/// Parses an IANA time zone name such as "Europe/Berlin".
+pub fn parse_timezone(input: &str) -> Result<Tz, String> {
+    input.trim().parse().map_err(|_| {
+        format!("Unknown time zone '{input}': expected an IANA name such as Europe/Berlin")
+    })
+}
+
+/// Parses a five-field cron expression ("minute hour day-of-month month
+/// day-of-week"). Besides ranges, lists and steps, `L`, `W` and `#` are
+/// understood, e.g. "0 9 * * MON#1" for 9:00 on each month's first Monday.
+pub fn parse_cron(input: &str) -> Result<Cron, String> {
+    CronParser::builder()
+        .seconds(Seconds::Disallowed)
+        .year(Year::Disallowed)
+        .build()
+        .parse(input)
+        .map_err(|e| format!("Invalid cron expression '{input}': {e}"))
+}
+
+/// Parses a point in time relative to `now`: a wall-clock time ("17:30",
+/// "5pm") meaning its next occurrence, "today 17:30", "tomorrow 09:00",
+/// "in 2h", or a date and time ("2026-10-20 14:00").
+pub fn parse_deadline(input: &str, now: DateTime<Local>) -> Result<DateTime<Local>, String> {
+    let deadline = resolve_deadline(input, now)?;
+    if deadline <= now {
+        return Err(format!("Invalid time '{}': time is in the past", input.trim()));
+    }
+    Ok(deadline)
+}
+
+/// Parses the end of a block that starts at `start`, in the formats of
+/// [`parse_deadline`] but relative to the start: "17:00" is its next
+/// occurrence after the start, and "in 2h" counts from it.
+pub fn parse_end(input: &str, start: DateTime<Local>) -> Result<DateTime<Local>, String> {
+    let end = resolve_deadline(input, start)?;
+    if end <= start {
+        return Err(format!(
+            "Invalid time '{}': the block would end before it starts at {}",
+            input.trim(),
+            start.format("%Y-%m-%d %H:%M")
+        ));
+    }
+    Ok(end)
+}
+
+/// [`parse_deadline`] without rejecting times before `now`.
+fn resolve_deadline(input: &str, now: DateTime<Local>) -> Result<DateTime<Local>, String> {
+    let trimmed = input.trim();
+    let lowered = trimmed.to_lowercase();
+
+    if let Some(duration) = lowered.strip_prefix("in ") {
+        return Ok(now + parse_duration(duration)?);
+    }
+
+    let (days_ahead, time_str) = if let Some(time) = lowered.strip_prefix("tomorrow") {
+        (Some(1), time)
+    } else if let Some(time) = lowered.strip_prefix("today") {
+        (Some(0), time)
+    } else if let Some((date, time)) = lowered.split_once(' ')
+        && let Ok(date) = parse_date(date)
+    {
+        (Some((date - now.date_naive()).num_days()), time)
+    } else {
+        (None, lowered.as_str())
+    };
+
+    let time = parse_time_of_day(time_str).map_err(|e| {
+        if days_ahead.is_some() && time_str.trim().is_empty() {
+            format!("Invalid time '{trimmed}': expected a time after the day (e.g. tomorrow 09:00)")
+        } else {
+            e
+        }
+    })?;
+
+    match days_ahead {
+        Some(days) => {
+            let date = now.date_naive() + Duration::days(days);
+            to_local(date.and_time(time))
+                .ok_or_else(|| format!("Invalid time '{trimmed}': time does not exist locally"))
+        }
+        None => Ok(next_occurrence(time, now)),
+    }
+}
+
+/// Clap-friendly wrapper around [`parse_deadline`] using the current time.
+pub fn parse_deadline_from_now(input: &str) -> Result<DateTime<Local>, String> {
+    parse_deadline(input, Local::now())
+}
+
+/// Parses a date ("2026-10-25", meaning its start) or a date and time
+/// ("2026-10-25 14:00") in local time. Unlike [`parse_deadline`], it may be
+/// in the past.
+pub fn parse_moment(input: &str) -> Result<DateTime<Local>, String> {
+    let trimmed = input.trim();
+    let (date, time) = match trimmed.split_once(' ') {
+        Some((date, time)) => (parse_date(date)?, parse_time_of_day(time)?),
+        None => (parse_date(trimmed)?, NaiveTime::MIN),
+    };
+    to_local(date.and_time(time))
+        .ok_or_else(|| format!("Invalid time '{trimmed}': time does not exist locally"))
+}
+
+/// Returns the next moment the given time of day occurs, which is tomorrow
+/// if it has already passed today.
+pub fn next_occurrence(time: NaiveTime, now: DateTime<Local>) -> DateTime<Local> {
+    let mut date = now.date_naive();
+    if time <= now.time() {
+        date += Duration::days(1);
+    }
+
+    to_local(date.and_time(time)).unwrap_or(now)
+}
+
+/// Resolves a wall-clock time in the local timezone. A time that occurs
+/// twice when clocks go back resolves to the first one, and a time skipped
+/// when clocks go forward resolves to an hour later.
+pub fn to_local(datetime: chrono::NaiveDateTime) -> Option<DateTime<Local>> {
+    in_zone(&Local, datetime)
+}
+
+/// Like [`to_local`], for a wall-clock time in any time zone.
+pub fn in_zone<Z: TimeZone>(zone: &Z, datetime: chrono::NaiveDateTime) -> Option<DateTime<Z>> {
+    zone.from_local_datetime(&datetime).earliest().or_else(|| {
+        zone.from_local_datetime(&(datetime + Duration::hours(1)))
+            .earliest()
+    })
+}
+
+fn parse_number(s: &str) -> Option<u32> {
+    if s.is_empty() || !s.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    s.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(h: u32, m: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(h, m, 0).unwrap()
+    }
+
+    #[test]
+    fn test_parse_duration_units() {
+        assert_eq!(parse_duration("90m").unwrap(), Duration::minutes(90));
+        assert_eq!(parse_duration("1h30m").unwrap(), Duration::minutes(90));
+        assert_eq!(parse_duration("1h 30m").unwrap(), Duration::minutes(90));
+        assert_eq!(parse_duration("2 hours").unwrap(), Duration::hours(2));
+        assert_eq!(parse_duration("45 MIN").unwrap(), Duration::minutes(45));
+        assert_eq!(parse_duration("45s").unwrap(), Duration::seconds(45));
+        assert_eq!(parse_duration("60").unwrap(), Duration::minutes(60));
+    }
+
+    #[test]
+    fn test_parse_duration_invalid() {
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("h").is_err());
+        assert!(parse_duration("-5m").is_err());
+        assert!(parse_duration("-5").is_err());
+
+        let error = parse_duration("10x").unwrap_err();
+        assert!(error.contains("unknown unit 'x'"));
+        let error = parse_duration("1h30").unwrap_err();
+        assert!(error.contains("missing unit after '30'"));
+    }
+
+    #[test]
+    fn test_parse_duration_out_of_range() {
+        let error = parse_duration("100000000000000h").unwrap_err();
+        assert!(error.contains("duration is out of range"), "{error}");
+        let error = parse_duration("99999999999999999999h").unwrap_err();
+        assert!(error.contains("number is too large"), "{error}");
+        let error = parse_duration("99999999999").unwrap_err();
+        assert!(error.contains("number is too large"), "{error}");
+    }
+
+    #[test]
+    fn test_parse_time_of_day_formats() {
+        assert_eq!(parse_time_of_day("17:00").unwrap(), time(17, 0));
+        assert_eq!(parse_time_of_day("9:30").unwrap(), time(9, 30));
+        assert_eq!(parse_time_of_day("5pm").unwrap(), time(17, 0));
+        assert_eq!(parse_time_of_day("5:30 AM").unwrap(), time(5, 30));
+        assert_eq!(parse_time_of_day("12am").unwrap(), time(0, 0));
+        assert_eq!(parse_time_of_day("12pm").unwrap(), time(12, 0));
+    }
+
+    #[test]
+    fn test_parse_time_of_day_errors() {
+        assert!(
+            parse_time_of_day("24:00")
+                .unwrap_err()
+                .contains("hour 24 is out of range")
+        );
+        assert!(
+            parse_time_of_day("9:75")
+                .unwrap_err()
+                .contains("minute 75 is out of range")
+        );
+        assert!(
+            parse_time_of_day("13pm")
+                .unwrap_err()
+                .contains("between 1 and 12")
+        );
+        assert!(parse_time_of_day("9:5").unwrap_err().contains("two digits"));
+        assert!(parse_time_of_day("noon").is_err());
+    }
+
+    #[test]
+    fn test_parse_days_single_days() {
+        for (input, day) in [
+            ("Mon", Weekday::Mon),
+            ("tue", Weekday::Tue),
+            ("WED", Weekday::Wed),
+            ("Thursday", Weekday::Thu),
+            ("friday", Weekday::Fri),
+            ("Sat", Weekday::Sat),
+            ("Sun", Weekday::Sun),
+        ] {
+            assert_eq!(parse_days(input), Ok(vec![day]), "{input}");
+        }
+    }
+
+    #[test]
+    fn test_parse_days_ranges_and_aliases() {
+        use Weekday::*;
+        assert_eq!(parse_days("Mon-Fri"), Ok(vec![Mon, Tue, Wed, Thu, Fri]));
+        assert_eq!(parse_days("fri - mon"), Ok(vec![Fri, Sat, Sun, Mon]));
+        assert_eq!(parse_days("Weekdays"), Ok(vec![Mon, Tue, Wed, Thu, Fri]));
+        assert_eq!(parse_days("weekends"), Ok(vec![Sat, Sun]));
+        assert_eq!(parse_days("daily").unwrap().len(), 7);
+    }
+
+    #[test]
+    fn test_parse_days_invalid() {
+        for input in ["xyz", "", "123", "Mo", "Mond", "Mon-", "Mon-Xyz"] {
+            let error = parse_days(input).unwrap_err();
+            assert!(
+                error.contains(&format!("Invalid day: '{input}'")),
+                "{error}"
+            );
+            assert!(error.contains("Valid days are:"));
+        }
+    }
+
+    #[test]
+    fn test_parse_deadline_relative() {
+        let now = Local.with_ymd_and_hms(2025, 9, 15, 18, 0, 0).unwrap();
+        assert_eq!(
+            parse_deadline("tomorrow 09:00", now).unwrap(),
+            Local.with_ymd_and_hms(2025, 9, 16, 9, 0, 0).unwrap()
+        );
+        assert_eq!(
+            parse_deadline("in 2h", now).unwrap(),
+            Local.with_ymd_and_hms(2025, 9, 15, 20, 0, 0).unwrap()
+        );
+        assert_eq!(
+            parse_deadline("2025-10-20 14:00", now).unwrap(),
+            Local.with_ymd_and_hms(2025, 10, 20, 14, 0, 0).unwrap()
+        );
+        assert!(
+            parse_deadline("2025-09-14 9am", now)
+                .unwrap_err()
+                .contains("in the past")
+        );
+        assert!(
+            parse_deadline("today 17:00", now)
+                .unwrap_err()
+                .contains("in the past")
+        );
+        assert!(
+            parse_deadline("tomorrow", now)
+                .unwrap_err()
+                .contains("expected a time")
+        );
+    }
+
+    #[test]
+    fn test_parse_end_counts_from_the_start() {
+        let now = Local.with_ymd_and_hms(2025, 9, 15, 18, 0, 0).unwrap();
+        let start = parse_deadline("2025-09-16 14:00", now).unwrap();
+        assert_eq!(
+            parse_end("17:00", start).unwrap(),
+            Local.with_ymd_and_hms(2025, 9, 16, 17, 0, 0).unwrap()
+        );
+        assert_eq!(
+            parse_end("in 2h", start).unwrap(),
+            Local.with_ymd_and_hms(2025, 9, 16, 16, 0, 0).unwrap()
+        );
+        assert!(
+            parse_end("2025-09-16 13:00", start)
+                .unwrap_err()
+                .contains("before it starts")
+        );
+    }
+
+    #[test]
+    fn test_next_occurrence_rolls_over_to_tomorrow() {
+        let now = Local.with_ymd_and_hms(2025, 9, 15, 18, 0, 0).unwrap();
+        let next = parse_deadline("17:30", now).unwrap();
+        assert_eq!(
+            next,
+            Local.with_ymd_and_hms(2025, 9, 16, 17, 30, 0).unwrap()
+        );
+    }
+}
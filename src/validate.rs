@@ -0,0 +1,658 @@
+use crate::apps::{self, ApplicationAction};
+use crate::calendar;
+use crate::config::{self, Config};
+use crate::private;
+use crate::timespec::{
+    parse_cron, parse_date, parse_days, parse_duration, parse_time_of_day, parse_timezone,
+};
+use chrono::Local;
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::error::Error;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use toml::Spanned;
+use toml::de::{DeTable, DeValue};
+
+/// A problem found in a config file. `position` is the 1-based line and
+/// column, when the problem can be tied to one. Warnings don't stop the
+/// config from loading.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub file: PathBuf,
+    pub position: Option<(usize, usize)>,
+    pub message: String,
+    pub warning: bool,
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:", self.file.display())?;
+        if let Some((line, column)) = self.position {
+            write!(f, "{line}:{column}:")?;
+        }
+        if self.warning {
+            write!(f, " warning:")?;
+        }
+        write!(f, " {}", self.message)
+    }
+}
+
+/// Checks a config file and its `conf.d` fragments without starting the
+/// daemon. Returns a report of every problem found, one
+/// `file:line:column: message` per line, or just the warnings if there are
+/// no errors.
+pub fn validate_file(path: &Path) -> Result<Vec<String>, String> {
+    let mut files = Vec::new();
+    for file in std::iter::once(path.to_path_buf()).chain(config::fragment_paths(path)?) {
+        let base_dir = path.parent().unwrap_or(Path::new("."));
+        let contents = config::read_fragment(&file, base_dir)?;
+        files.push((file, contents));
+    }
+
+    let diagnostics = validate_files(&files, || {
+        config::parse_config(Some(path.display().to_string()))
+    });
+    let report: Vec<String> = diagnostics.iter().map(Diagnostic::to_string).collect();
+    if diagnostics.iter().all(|d| d.warning) {
+        return Ok(report);
+    }
+    Err(report.join("\n"))
+}
+
+#[cfg(test)]
+pub fn validate_str(contents: &str, base_dir: &Path) -> Vec<Diagnostic> {
+    let files = [(base_dir.join("shire.toml"), contents.to_string())];
+    validate_files(&files, || config::parse_config_str(contents, base_dir))
+}
+
+/// Checks the main config file and its fragments together, so a schedule
+/// in one file may refer to a block defined in another. `full_parse` loads
+/// the merged config to catch anything the per-file checks can't.
+fn validate_files(
+    files: &[(PathBuf, String)],
+    full_parse: impl FnOnce() -> Result<Config, Box<dyn Error>>,
+) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+
+    let mut roots = Vec::new();
+    for (file, contents) in files {
+        let mut checker = Checker::new(file, contents, &mut diagnostics);
+        match DeTable::parse(contents) {
+            Ok(root) => roots.push((file, contents, root)),
+            Err(e) => checker.report_toml_error(&e),
+        }
+    }
+    if !diagnostics.is_empty() {
+        return diagnostics;
+    }
+
+    let mut names = Names::default();
+    for (file, contents, root) in &roots {
+        Checker::new(file, contents, &mut diagnostics).collect_names(root.get_ref(), &mut names);
+    }
+    for (file, contents, root) in &roots {
+        let mut checker = Checker::new(file, contents, &mut diagnostics);
+        checker.check(root.get_ref(), &names);
+
+        // Type errors come from deserializing into `Config`
+        let contents = if private::is_encrypted(file) {
+            Cow::Owned(without_aliases(contents, root.get_ref()))
+        } else {
+            Cow::Borrowed(contents.as_str())
+        };
+        if let Err(e) = toml::from_str::<Config>(&contents) {
+            checker.report_toml_error(&e);
+        }
+    }
+
+    // Anything left (include cycles, pattern files) is only caught by the
+    // full parse, which stops at the first error
+    if diagnostics.is_empty() {
+        match full_parse() {
+            Ok(config) => {
+                let now = Local::now();
+                for (first, second) in config::schedule_overlaps(&config.schedule, now) {
+                    let (first, second) = (&config.schedule[first], &config.schedule[second]);
+                    diagnostics.push(Diagnostic {
+                        file: files[0].0.clone(),
+                        position: None,
+                        message: format!(
+                            "Schedules for block '{}' overlap: {} and {}. The block stays active until both have ended.",
+                            first.block,
+                            first.describe(),
+                            second.describe()
+                        ),
+                        warning: true,
+                    });
+                }
+                // The daemon skips a calendar it can't read, so say so now
+                for schedule in &config.schedule {
+                    if let Ok(Some((path, title))) = schedule.calendar()
+                        && let Err(e) = calendar::load_windows(path, title, now, now)
+                    {
+                        diagnostics.push(Diagnostic {
+                            file: files[0].0.clone(),
+                            position: None,
+                            message: format!("In schedule for block '{}': {}", schedule.block, e),
+                            warning: false,
+                        });
+                    }
+                }
+            }
+            Err(e) => diagnostics.push(Diagnostic {
+                file: files[0].0.clone(),
+                position: None,
+                message: e.to_string(),
+                warning: false,
+            }),
+        }
+    }
+
+    let file_index = |d: &Diagnostic| files.iter().position(|(file, _)| *file == d.file);
+    diagnostics.sort_by_key(|d| (file_index(d), d.position));
+    diagnostics
+}
+
+/// Block, session and schedule names defined across all files.
+#[derive(Default)]
+struct Names<'a> {
+    blocks: HashSet<&'a str>,
+    sessions: HashSet<&'a str>,
+    schedules: HashSet<&'a str>,
+}
+
+struct Checker<'a> {
+    file: &'a Path,
+    contents: &'a str,
+    diagnostics: &'a mut Vec<Diagnostic>,
+}
+
+impl<'a> Checker<'a> {
+    fn new(file: &'a Path, contents: &'a str, diagnostics: &'a mut Vec<Diagnostic>) -> Self {
+        Checker {
+            file,
+            contents,
+            diagnostics,
+        }
+    }
+
+    fn collect_names<'t>(&mut self, root: &'t DeTable<'_>, names: &mut Names<'t>) {
+        for (key, seen, kind) in [
+            ("blocks", &mut names.blocks, "block"),
+            ("sessions", &mut names.sessions, "session"),
+            ("schedule", &mut names.schedules, "schedule"),
+        ] {
+            for table in array_of_tables(root, key) {
+                if let Some(name) = table.get("name")
+                    && let Some(value) = name.get_ref().as_str()
+                    && !seen.insert(value)
+                {
+                    self.error(name.span(), format!("Duplicate {kind} name: '{value}'."));
+                }
+            }
+        }
+
+        // Other files refer to private blocks by their alias
+        if private::is_encrypted(self.file) {
+            for block in array_of_tables(root, "blocks") {
+                if let Some(alias) = block.get("alias")
+                    && let Some(value) = alias.get_ref().as_str()
+                    && !names.blocks.insert(value)
+                {
+                    self.error(alias.span(), format!("Duplicate block name: '{value}'."));
+                }
+            }
+        }
+    }
+
+    fn check(&mut self, root: &DeTable<'_>, names: &Names) {
+        self.check_keys(root, config::field_names::<Config>(), "the top level");
+        self.check_value(root, "timezone", |value| parse_timezone(value).map(|_| ()));
+
+        // Only encrypted fragments may give their blocks an alias
+        let mut block_keys = config::field_names::<config::Block>().to_vec();
+        if private::is_encrypted(self.file) {
+            block_keys.push("alias");
+        }
+        for block in array_of_tables(root, "blocks") {
+            self.check_keys(block, &block_keys, "[[blocks]]");
+            for key in ["unblock_delay", "daily_allowance"] {
+                self.check_value(block, key, |value| {
+                    parse_duration(value)
+                        .map(|_| ())
+                        .map_err(|e| format!("Invalid {key}: {e}"))
+                });
+            }
+            self.check_value(block, "application_action", |value| {
+                ApplicationAction::parse(value)
+                    .map(|_| ())
+                    .map_err(|e| format!("Invalid application_action: {e}"))
+            });
+            let applications = block.get("applications").and_then(|a| a.get_ref().as_array());
+            for application in applications.iter().flat_map(|values| values.iter()) {
+                if let Some(value) = application.get_ref().as_str()
+                    && let Err(e) = apps::check_pattern(value)
+                {
+                    self.error(application.span(), format!("Invalid applications entry: {e}"));
+                }
+            }
+        }
+
+        for schedule in array_of_tables(root, "schedule") {
+            let keys = config::field_names::<config::Schedule>();
+            self.check_keys(schedule, keys, "[[schedule]]");
+            self.check_value(schedule, "block", |value| {
+                if names.blocks.contains(value) {
+                    Ok(())
+                } else {
+                    Err(format!(
+                        "Schedule references non-existent block: '{value}'."
+                    ))
+                }
+            });
+            if let Some(days) = schedule.get("days").and_then(|d| d.get_ref().as_array()) {
+                for day in days.iter() {
+                    if let Some(value) = day.get_ref().as_str()
+                        && let Err(e) = parse_days(value)
+                    {
+                        self.error(day.span(), e);
+                    }
+                }
+            }
+            for key in ["start", "end"] {
+                self.check_value(schedule, key, |value| {
+                    parse_time_of_day(value)
+                        .map(|_| ())
+                        .map_err(|e| format!("Invalid {key} time: {e}"))
+                });
+            }
+            self.check_value(schedule, "cron", |value| parse_cron(value).map(|_| ()));
+            self.check_value(schedule, "duration", |value| {
+                config::parse_window_duration(value).map(|_| ())
+            });
+            for key in ["valid_from", "valid_until"] {
+                self.check_value(schedule, key, |value| parse_date(value).map(|_| ()));
+            }
+            self.check_value(schedule, "timezone", |value| parse_timezone(value).map(|_| ()));
+        }
+
+        for exception in array_of_tables(root, "exceptions") {
+            let keys = config::field_names::<config::Exception>();
+            self.check_keys(exception, keys, "[[exceptions]]");
+            for key in ["date", "from", "until"] {
+                self.check_value(exception, key, |value| parse_date(value).map(|_| ()));
+            }
+            for (key, known, kind) in [
+                ("blocks", &names.blocks, "block"),
+                ("schedules", &names.schedules, "schedule"),
+            ] {
+                let Some(values) = exception.get(key).and_then(|v| v.get_ref().as_array()) else {
+                    continue;
+                };
+                for value in values.iter() {
+                    if let Some(name) = value.get_ref().as_str()
+                        && !known.contains(name)
+                    {
+                        self.error(
+                            value.span(),
+                            format!("Exception references non-existent {kind}: '{name}'."),
+                        );
+                    }
+                }
+            }
+        }
+
+        for session in array_of_tables(root, "sessions") {
+            self.check_keys(session, config::field_names::<config::Session>(), "[[sessions]]");
+            if let Some(blocks) = session.get("blocks").and_then(|b| b.get_ref().as_array()) {
+                for block in blocks.iter() {
+                    if let Some(value) = block.get_ref().as_str()
+                        && !names.blocks.contains(value)
+                    {
+                        self.error(
+                            block.span(),
+                            format!("Session references non-existent block: '{value}'."),
+                        );
+                    }
+                }
+            }
+            self.check_value(session, "duration", |value| {
+                parse_duration(value)
+                    .map(|_| ())
+                    .map_err(|e| format!("Invalid duration: {e}"))
+            });
+        }
+
+        if let Some(lists) = root.get("lists").and_then(|l| l.get_ref().as_table()) {
+            for (name, list) in lists.iter() {
+                if let Some(list) = list.get_ref().as_table() {
+                    self.check_keys(
+                        list,
+                        config::field_names::<config::PatternList>(),
+                        &format!("[lists.{}]", name.get_ref()),
+                    );
+                }
+            }
+        }
+    }
+
+    fn check_keys(&mut self, table: &DeTable<'_>, known: &[&str], context: &str) {
+        for key in table.keys() {
+            if !known.contains(&key.get_ref().as_ref()) {
+                self.error(
+                    key.span(),
+                    format!("Unknown key '{}' in {context}.", key.get_ref()),
+                );
+            }
+        }
+    }
+
+    /// Runs `check` on a string field, reporting its error at the value.
+    fn check_value(
+        &mut self,
+        table: &DeTable<'_>,
+        key: &str,
+        check: impl FnOnce(&str) -> Result<(), String>,
+    ) {
+        if let Some(value) = table.get(key)
+            && let Some(text) = value.get_ref().as_str()
+            && let Err(e) = check(text)
+        {
+            self.error(value.span(), e);
+        }
+    }
+
+    fn report_toml_error(&mut self, error: &toml::de::Error) {
+        let position = error.span().map(|span| self.position(span.start));
+        // Unknown keys are already reported by `check_keys`, which finds all
+        // of them rather than stopping at the first
+        let reported = position.is_some()
+            && self
+                .diagnostics
+                .iter()
+                .any(|found| found.position == position && found.file == self.file);
+        if reported {
+            return;
+        }
+        self.diagnostics.push(Diagnostic {
+            file: self.file.to_path_buf(),
+            position,
+            message: error.message().trim_end().to_string(),
+            warning: false,
+        });
+    }
+
+    fn error(&mut self, span: Range<usize>, message: String) {
+        let position = Some(self.position(span.start));
+        self.diagnostics.push(Diagnostic {
+            file: self.file.to_path_buf(),
+            position,
+            message,
+            warning: false,
+        });
+    }
+
+    fn position(&self, offset: usize) -> (usize, usize) {
+        let before = &self.contents[..offset.min(self.contents.len())];
+        let line = before.matches('\n').count() + 1;
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        (line, before[line_start..].chars().count() + 1)
+    }
+}
+
+/// `contents` with the blocks' `alias` keys blanked out, keeping every other
+/// position, since loading removes them before deserializing.
+fn without_aliases(contents: &str, root: &DeTable<'_>) -> String {
+    let mut blanked = contents.to_string();
+    for block in array_of_tables(root, "blocks") {
+        if let Some((key, value)) = block.get_key_value("alias") {
+            let span = key.span().start..value.span().end;
+            let blank: String = contents[span.clone()]
+                .chars()
+                .map(|c| if c == '\n' { "\n".to_string() } else { " ".repeat(c.len_utf8()) })
+                .collect();
+            blanked.replace_range(span, &blank);
+        }
+    }
+    blanked
+}
+
+fn array_of_tables<'t, 'i>(root: &'t DeTable<'i>, key: &str) -> Vec<&'t DeTable<'i>> {
+    root.get(key)
+        .and_then(|value| value.get_ref().as_array())
+        .map(|entries| {
+            entries
+                .iter()
+                .filter_map(|entry: &Spanned<DeValue<'i>>| entry.get_ref().as_table())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn messages(contents: &str) -> Vec<(Option<(usize, usize)>, String)> {
+        validate_str(contents, Path::new("."))
+            .into_iter()
+            .map(|d| (d.position, d.message))
+            .collect()
+    }
+
+    #[test]
+    fn test_valid_config_has_no_diagnostics() {
+        let contents = r#"
+[[blocks]]
+name = "feeds"
+blacklist = ["reddit.com"]
+
+[[schedule]]
+block = "feeds"
+days = ["Mon", "Tue"]
+start = "9:00"
+end = "5pm"
+"#;
+        assert!(messages(contents).is_empty());
+    }
+
+    #[test]
+    fn test_reports_every_error_with_position() {
+        let contents = r#"
+[[blocks]]
+name = "feeds"
+blaklist = ["reddit.com"]
+
+[[blocks]]
+name = "feeds"
+unblock_delay = "soon"
+
+[[schedule]]
+block = "news"
+days = ["Mon", "Someday"]
+start = "9:00"
+end = "25:00"
+"#;
+        let found = messages(contents);
+        let positions: Vec<_> = found.iter().map(|(p, _)| p.unwrap()).collect();
+        assert_eq!(
+            positions,
+            [(4, 1), (7, 8), (8, 17), (11, 9), (12, 16), (14, 7)]
+        );
+        assert_eq!(found[0].1, "Unknown key 'blaklist' in [[blocks]].");
+        assert_eq!(found[1].1, "Duplicate block name: 'feeds'.");
+        assert!(found[2].1.starts_with("Invalid unblock_delay"));
+        assert_eq!(
+            found[3].1,
+            "Schedule references non-existent block: 'news'."
+        );
+        assert!(found[4].1.starts_with("Invalid day: 'Someday'"));
+        assert!(found[5].1.starts_with("Invalid end time"));
+    }
+
+    #[test]
+    fn test_schedule_dates_zones_and_exceptions_are_checked() {
+        let contents = r#"
+[[blocks]]
+name = "feeds"
+
+[[schedule]]
+name = "work"
+block = "feeds"
+days = ["weekdays"]
+start = "9:00"
+end = "17:00"
+valid_until = "2026-13-01"
+timezone = "Mars/Olympus"
+
+[[exceptions]]
+from = "2026-12-24"
+until = "2026-12-26"
+schedules = ["work", "gym"]
+blocks = ["feeds"]
+"#;
+        let found = messages(contents);
+        assert_eq!(found.len(), 3);
+        assert_eq!(found[0].0, Some((11, 15)));
+        assert!(found[0].1.starts_with("Invalid date '2026-13-01'"));
+        assert_eq!(found[1].0, Some((12, 12)));
+        assert!(found[1].1.starts_with("Unknown time zone 'Mars/Olympus'"));
+        assert_eq!(found[2].0, Some((17, 22)));
+        assert_eq!(
+            found[2].1,
+            "Exception references non-existent schedule: 'gym'."
+        );
+    }
+
+    #[test]
+    fn test_syntax_and_type_errors_have_positions() {
+        let syntax = messages("schedule = [\n[[blocks]]\n");
+        assert_eq!(syntax.len(), 1);
+        assert!(syntax[0].0.is_some());
+
+        let missing = messages("[[blocks]]\nblacklist = [\"reddit.com\"]\n");
+        assert_eq!(missing.len(), 1);
+        assert!(missing[0].1.contains("missing field `name`"));
+
+        let wrong_type = messages("schedule = []\n\n[[blocks]]\nname = 5\n");
+        assert_eq!(wrong_type[0].0, Some((4, 8)));
+    }
+
+    #[test]
+    fn test_aliases_are_only_allowed_in_encrypted_fragments() {
+        let files = [
+            (
+                PathBuf::from("shire.toml"),
+                "[[blocks]]\nname = \"feeds\"\nprivate = true\nalias = \"news\"\n".to_string(),
+            ),
+            (
+                PathBuf::from("conf.d/private.toml.enc"),
+                "[[blocks]]\nname = \"pokér\"\nalias = \"évenings\"\nblacklist = 5\n".to_string(),
+            ),
+        ];
+        let found: Vec<_> = validate_files(&files, || unreachable!())
+            .into_iter()
+            .map(|d| (d.file.display().to_string(), d.position, d.message))
+            .collect();
+        assert_eq!(found.len(), 3, "{found:?}");
+        assert_eq!(found[0].1, Some((3, 1)));
+        assert_eq!(found[0].2, "Unknown key 'private' in [[blocks]].");
+        assert_eq!(found[1].2, "Unknown key 'alias' in [[blocks]].");
+        assert_eq!(found[2].0, "conf.d/private.toml.enc");
+        assert_eq!(found[2].1, Some((4, 13)));
+        assert!(found[2].2.contains("invalid type"), "{}", found[2].2);
+    }
+
+    #[test]
+    fn test_broad_application_patterns_are_errors() {
+        let contents = r#"
+[[blocks]]
+name = "games"
+applications = ["steam", "", "/usr/"]
+"#;
+        let found = messages(contents);
+        let positions: Vec<_> = found.iter().map(|(p, _)| p.unwrap()).collect();
+        assert_eq!(positions, [(4, 26), (4, 30)]);
+        assert_eq!(found[0].1, "Invalid applications entry: the pattern is empty");
+        assert!(found[1].1.contains("'/usr/' is too broad"));
+    }
+
+    #[test]
+    fn test_cron_schedules_are_checked() {
+        let contents = r#"
+[[blocks]]
+name = "feeds"
+
+[[schedule]]
+block = "feeds"
+cron = "0 9 * * MON#1"
+duration = "3h"
+
+[[schedule]]
+block = "feeds"
+cron = "0 9 * *"
+duration = "2 days"
+"#;
+        let found = messages(contents);
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].0, Some((12, 8)));
+        assert!(found[0].1.starts_with("Invalid cron expression '0 9 * *'"));
+        assert_eq!(found[1].0, Some((13, 12)));
+        assert!(found[1].1.contains("between 1 minute and 24 hours"));
+
+        let mixed = messages(
+            "[[blocks]]\nname = \"feeds\"\n\n[[schedule]]\nblock = \"feeds\"\ndays = [\"Mon\"]\ncron = \"0 9 * * *\"\nduration = \"1h\"\n",
+        );
+        assert_eq!(mixed.len(), 1);
+        assert!(mixed[0].1.contains("use only one of"));
+    }
+
+    #[test]
+    fn test_overlapping_schedules_are_warnings() {
+        let contents = r#"
+[[blocks]]
+name = "feeds"
+blacklist = ["reddit.com"]
+
+[[schedule]]
+block = "feeds"
+days = ["Mon-Fri"]
+start = "9:00"
+end = "17:00"
+
+[[schedule]]
+block = "feeds"
+days = ["Fri", "Sat"]
+start = "16:00"
+end = "2:00"
+
+[[schedule]]
+block = "feeds"
+days = ["Sun"]
+start = "9:00"
+end = "17:00"
+"#;
+        let diagnostics = validate_str(contents, Path::new("."));
+        assert_eq!(diagnostics.len(), 1);
+        assert!(diagnostics[0].warning);
+        assert!(diagnostics[0].message.contains("Mon-Fri 9:00-17:00"));
+        assert!(diagnostics[0].to_string().contains(": warning: "));
+    }
+
+    #[test]
+    fn test_unreadable_calendars_are_errors() {
+        let contents = r#"
+[[blocks]]
+name = "feeds"
+
+[[schedule]]
+block = "feeds"
+ics = "missing.ics"
+title = "Focus"
+"#;
+        let found = messages(contents);
+        assert_eq!(found.len(), 1);
+        assert!(found[0].1.contains("Failed to read calendar"), "{}", found[0].1);
+    }
+}